@@ -1,5 +1,6 @@
 use async_memcached::Client;
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::io::Write;
 use tokio::runtime::Runtime;
 
 const LARGE_PAYLOAD_SIZE: usize = 1000 * 1024; // Memcached's ~default maximum payload size
@@ -30,6 +31,35 @@ fn bench_get(c: &mut Criterion) {
     });
 }
 
+fn bench_get_with_key_writer(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    rt.block_on(async {
+        let mut client = setup_client().await;
+        for i in 0..100 {
+            client
+                .set(format!("key:{:06}", i), "bar", None, None)
+                .await
+                .unwrap();
+        }
+    });
+
+    c.bench_function("get_with_key_writer", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut client = setup_client().await;
+            let start = std::time::Instant::now();
+            for i in 0..iters {
+                let _ = client
+                    .get_with_key_writer(|key| {
+                        let _ = write!(key, "key:{:06}", i % 100);
+                    })
+                    .await;
+            }
+            start.elapsed()
+        });
+    });
+}
+
 fn bench_set_with_string(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
 
@@ -63,6 +93,27 @@ fn bench_set_with_large_string(c: &mut Criterion) {
     });
 }
 
+fn bench_set_large_throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    let mut group = c.benchmark_group("set_large_throughput");
+    group.throughput(Throughput::Bytes(LARGE_PAYLOAD_SIZE as u64));
+    group.bench_function("set", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut client = setup_client().await;
+            let large_payload = vec![b'a'; LARGE_PAYLOAD_SIZE];
+            let start = std::time::Instant::now();
+            for _ in 0..iters {
+                let _ = client
+                    .set("large_foo", large_payload.as_slice(), None, None)
+                    .await;
+            }
+            start.elapsed()
+        });
+    });
+    group.finish();
+}
+
 fn bench_set_with_u64(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
 
@@ -131,6 +182,86 @@ fn bench_get_many(c: &mut Criterion) {
     });
 }
 
+fn bench_get_multi_vs_pipelined(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let keys: Vec<String> = (0..1000).map(|i| format!("pipelined:{}", i)).collect();
+
+    rt.block_on(async {
+        let mut client = setup_client().await;
+        for key in &keys {
+            client.set(key, "zzz", None, None).await.unwrap();
+        }
+    });
+
+    c.bench_function("get_multi_1000_keys", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let keys = keys.clone();
+            async move {
+                let mut client = setup_client().await;
+                let start = std::time::Instant::now();
+                for _ in 0..iters {
+                    let _ = client.get_multi(&keys).await;
+                }
+                start.elapsed()
+            }
+        });
+    });
+
+    c.bench_function("get_multi_pipelined_1000_keys", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let keys = keys.clone();
+            async move {
+                let mut client = setup_client().await;
+                let start = std::time::Instant::now();
+                for _ in 0..iters {
+                    let _ = client.get_multi_pipelined(&keys, 4).await;
+                }
+                start.elapsed()
+            }
+        });
+    });
+}
+
+fn bench_get_multi_borrowed_vs_owned_keys(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let keys: Vec<String> = (0..10_000).map(|i| format!("borrowed:{}", i)).collect();
+
+    rt.block_on(async {
+        let mut client = setup_client().await;
+        for key in &keys {
+            client.set(key, "zzz", None, None).await.unwrap();
+        }
+    });
+
+    c.bench_function("get_multi_10000_borrowed_keys", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let keys = keys.clone();
+            async move {
+                let mut client = setup_client().await;
+                let start = std::time::Instant::now();
+                for _ in 0..iters {
+                    let _ = client.get_multi(keys.as_slice()).await;
+                }
+                start.elapsed()
+            }
+        });
+    });
+
+    c.bench_function("get_multi_10000_owned_keys", |b| {
+        b.to_async(&rt).iter_custom(|iters| {
+            let keys = keys.clone();
+            async move {
+                let mut client = setup_client().await;
+                let start = std::time::Instant::now();
+                for _ in 0..iters {
+                    let _ = client.get_multi(keys.clone()).await;
+                }
+                start.elapsed()
+            }
+        });
+    });
+}
+
 fn bench_add_with_large_string(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
 
@@ -229,6 +360,72 @@ fn bench_get_large(c: &mut Criterion) {
     });
 }
 
+fn bench_get_large_bytes(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let large_payload = "a".repeat(LARGE_PAYLOAD_SIZE);
+
+    rt.block_on(async {
+        let mut client = setup_client().await;
+        client
+            .set("large_foo_bytes", large_payload.as_str(), None, None)
+            .await
+            .unwrap();
+    });
+
+    c.bench_function("get_large_bytes", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut client = setup_client().await;
+            let start = std::time::Instant::now();
+            for _ in 0..iters {
+                let _ = client.get_bytes("large_foo_bytes").await;
+            }
+            start.elapsed()
+        });
+    });
+}
+
+fn bench_get_large_read_chunk_size(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let large_payload = "a".repeat(LARGE_PAYLOAD_SIZE);
+
+    rt.block_on(async {
+        let mut client = setup_client().await;
+        client
+            .set("large_foo", large_payload.as_str(), None, None)
+            .await
+            .unwrap();
+    });
+
+    // A new client reads each large value in 1KB chunks, whereas a client which has already read
+    // large values has grown its read chunk size to match them.
+    c.bench_function("get_large_new_client", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut elapsed = std::time::Duration::ZERO;
+            for _ in 0..iters {
+                let mut client = setup_client().await;
+                let start = std::time::Instant::now();
+                let _ = client.get("large_foo").await;
+                elapsed += start.elapsed();
+            }
+            elapsed
+        });
+    });
+
+    c.bench_function("get_large_adapted_client", |b| {
+        b.to_async(&rt).iter_custom(|iters| async move {
+            let mut client = setup_client().await;
+            for _ in 0..8 {
+                let _ = client.get("large_foo").await;
+            }
+            let start = std::time::Instant::now();
+            for _ in 0..iters {
+                let _ = client.get("large_foo").await;
+            }
+            start.elapsed()
+        });
+    });
+}
+
 fn bench_get_many_large(c: &mut Criterion) {
     let rt = Runtime::new().unwrap();
     let large_payload = "a".repeat(LARGE_PAYLOAD_SIZE);
@@ -342,11 +539,17 @@ fn bench_decrement_no_reply(c: &mut Criterion) {
 criterion_group!(
     benches,
     bench_get,
+    bench_get_with_key_writer,
     bench_get_many,
+    bench_get_multi_vs_pipelined,
+    bench_get_multi_borrowed_vs_owned_keys,
     bench_get_large,
+    bench_get_large_bytes,
+    bench_get_large_read_chunk_size,
     bench_get_many_large,
     bench_set_with_string,
     bench_set_with_large_string,
+    bench_set_large_throughput,
     bench_set_with_u64,
     bench_set_multi_small_strings,
     bench_set_multi_with_100_large_string_values,
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use crate::connection::Connection;
+#[cfg(feature = "socks")]
+use crate::connection::ProxyConfig;
+use crate::{Client, Error};
+
+/// Builder for a [`Client`], to configure its connection before making it.
+///
+/// [`Client::new`] is equivalent to connecting with a builder left at its defaults: no connect
+/// timeout, `TCP_NODELAY` set on TCP connections, and a read buffer which starts at 1KB.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), async_memcached::Error> {
+/// use async_memcached::Client;
+/// use std::time::Duration;
+///
+/// let client = Client::builder()
+///     .connect_timeout(Duration::from_secs(1))
+///     .read_buffer_capacity(64 * 1024)
+///     .connect("tcp://127.0.0.1:11211")
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientBuilder {
+    connect_timeout: Option<Duration>,
+    tcp_nodelay: bool,
+    read_buffer_capacity: Option<usize>,
+    operation_timeout: Option<Duration>,
+    #[cfg(feature = "socks")]
+    proxy: Option<ProxyConfig>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            connect_timeout: None,
+            tcp_nodelay: true,
+            read_buffer_capacity: None,
+            operation_timeout: None,
+            #[cfg(feature = "socks")]
+            proxy: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Creates a new [`ClientBuilder`] with the defaults used by [`Client::new`].
+    pub fn new() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Limits how long connecting may take, including resolving the server's address.
+    ///
+    /// If the connection is not made in time, [`Error::Timeout`] is returned for the `connect`
+    /// operation.  By default, connecting is not limited.
+    pub fn connect_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets whether `TCP_NODELAY` is set on TCP connections, which it is by default.
+    ///
+    /// Commands are flushed as soon as they are written, so disabling it only delays small
+    /// commands in favour of fewer packets.  This has no effect on UNIX domain sockets.
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> ClientBuilder {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    /// Sets the smallest number of bytes reserved in the read buffer before each read from the
+    /// connection.
+    ///
+    /// The read buffer adapts to the size of responses, starting at 1KB, so this mostly saves the
+    /// first few reads of a workload with consistently large values.  Values below 1KB are
+    /// rounded up to it.
+    pub fn read_buffer_capacity(mut self, capacity: usize) -> ClientBuilder {
+        self.read_buffer_capacity = Some(capacity);
+        self
+    }
+
+    /// Limits how long each read of a response may wait for data from the server.
+    ///
+    /// The timeout applies to each read rather than to a whole command, so a large response which
+    /// keeps arriving is never cut off, while a server which stops responding is.  If a read times
+    /// out, [`Error::Timeout`] is returned for the `read` operation, and as the rest of the
+    /// response may still arrive, the client is poisoned.  It can be recovered with
+    /// [`Client::drain`](crate::Client::drain) once the server responds, or discarded.  By
+    /// default, reads are not limited.
+    pub fn operation_timeout(mut self, timeout: Duration) -> ClientBuilder {
+        self.operation_timeout = Some(timeout);
+        self
+    }
+
+    /// Tunnels the connection through the given SOCKS5 proxy.
+    ///
+    /// The target address is resolved by the proxy, not locally.  Only TCP DSNs can be proxied,
+    /// so connecting to a UNIX domain socket DSN returns [`Error::Connect`].  The connect timeout
+    /// covers the handshake with the proxy, and `TCP_NODELAY` is set on the proxied connection as
+    /// configured.
+    ///
+    /// Only available with the `socks` feature.
+    #[cfg(feature = "socks")]
+    pub fn proxy(mut self, proxy: ProxyConfig) -> ClientBuilder {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Connects to the given data source string, as accepted by [`Client::new`].
+    pub async fn connect<S: AsRef<str>>(self, dsn: S) -> Result<Client, Error> {
+        let dsn = dsn.as_ref();
+        let connect = self.connection(dsn);
+        let connection = match self.connect_timeout {
+            Some(after) => {
+                tokio::time::timeout(after, connect)
+                    .await
+                    .map_err(|_| Error::Timeout {
+                        operation: "connect",
+                        endpoint: dsn.to_string(),
+                        after,
+                    })??
+            }
+            None => connect.await?,
+        };
+
+        let mut client = Client::from_connection(connection);
+        client.options.read_buffer_capacity = self.read_buffer_capacity;
+        client.options.operation_timeout = self.operation_timeout;
+        client.read_chunk_size = client.min_read_chunk_size();
+        Ok(client)
+    }
+
+    /// Connects to the given DSN, through the proxy if one was given.
+    async fn connection(&self, dsn: &str) -> Result<Connection, Error> {
+        #[cfg(feature = "socks")]
+        if let Some(proxy) = &self.proxy {
+            return Connection::new_with_proxy(dsn, proxy, self.tcp_nodelay).await;
+        }
+        Connection::new(dsn, self.tcp_nodelay).await
+    }
+}
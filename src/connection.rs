@@ -1,17 +1,89 @@
 use pin_project::pin_project;
+use std::fmt;
 use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, BufReader, BufWriter};
 use tokio::net::{lookup_host, TcpSocket, TcpStream, UnixStream};
+#[cfg(feature = "socks")]
+use tokio_socks::tcp::Socks5Stream;
 
 use crate::Error;
 
+/// Configuration for tunneling TCP connections through a SOCKS5 proxy.
+#[cfg(feature = "socks")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyConfig {
+    /// Address of the SOCKS5 proxy, in the format of `<host>:<port>`.
+    pub addr: String,
+    /// Username and password to authenticate with the proxy, if it requires them.
+    pub credentials: Option<(String, String)>,
+}
+
+#[cfg(feature = "socks")]
+impl ProxyConfig {
+    /// Creates a new [`ProxyConfig`] for an unauthenticated proxy at the given address.
+    pub fn new<S: Into<String>>(addr: S) -> Self {
+        ProxyConfig {
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    /// Sets the username and password used to authenticate with the proxy.
+    pub fn with_credentials<U: Into<String>, P: Into<String>>(
+        mut self,
+        username: U,
+        password: P,
+    ) -> Self {
+        self.credentials = Some((username.into(), password.into()));
+        self
+    }
+}
+
+/// A bidirectional stream over which a [`Client`](crate::Client) can communicate.
+///
+/// This is implemented for every type which implements the required traits, and allows custom
+/// transports and middleware to be used through [`Client::from_stream`](crate::Client::from_stream)
+/// and [`Client::wrap_stream`](crate::Client::wrap_stream).
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+/// Details of the connection underlying a [`Client`](crate::Client), for debugging and logging.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Local address of the socket, for TCP connections.
+    pub local_addr: Option<SocketAddr>,
+    /// Address of the server, for TCP connections.
+    pub peer_addr: Option<SocketAddr>,
+    /// Path of the server's socket, for UNIX domain socket connections.
+    pub socket_path: Option<PathBuf>,
+    /// Whether the socket is still connected to the server, on a best-effort basis.
+    ///
+    /// This is based on whether the server's address can still be retrieved from the socket, so
+    /// a connection closed by the server may only be noticed once it is next read from.  Custom
+    /// streams are always reported as connected.
+    pub connected: bool,
+}
+
 #[pin_project(project = ConnectionProjection)]
-#[derive(Debug)]
 pub enum Connection {
     Tcp(#[pin] BufReader<BufWriter<TcpStream>>),
     Unix(#[pin] BufReader<BufWriter<UnixStream>>),
+    Custom(#[pin] BufReader<BufWriter<Box<dyn AsyncStream>>>),
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Connection::Tcp(s) => f.debug_tuple("Tcp").field(s).finish(),
+            Connection::Unix(s) => f.debug_tuple("Unix").field(s).finish(),
+            Connection::Custom(_) => f.debug_tuple("Custom").finish(),
+        }
+    }
 }
 
 impl AsyncRead for Connection {
@@ -23,6 +95,7 @@ impl AsyncRead for Connection {
         match self.project() {
             ConnectionProjection::Tcp(s) => s.poll_read(cx, buf),
             ConnectionProjection::Unix(s) => s.poll_read(cx, buf),
+            ConnectionProjection::Custom(s) => s.poll_read(cx, buf),
         }
     }
 }
@@ -32,6 +105,27 @@ impl AsyncWrite for Connection {
         match self.project() {
             ConnectionProjection::Tcp(s) => s.poll_write(cx, buf),
             ConnectionProjection::Unix(s) => s.poll_write(cx, buf),
+            ConnectionProjection::Custom(s) => s.poll_write(cx, buf),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        match self.project() {
+            ConnectionProjection::Tcp(s) => s.poll_write_vectored(cx, bufs),
+            ConnectionProjection::Unix(s) => s.poll_write_vectored(cx, bufs),
+            ConnectionProjection::Custom(s) => s.poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Connection::Tcp(s) => s.is_write_vectored(),
+            Connection::Unix(s) => s.is_write_vectored(),
+            Connection::Custom(s) => s.is_write_vectored(),
         }
     }
 
@@ -39,6 +133,7 @@ impl AsyncWrite for Connection {
         match self.project() {
             ConnectionProjection::Tcp(s) => s.poll_flush(cx),
             ConnectionProjection::Unix(s) => s.poll_flush(cx),
+            ConnectionProjection::Custom(s) => s.poll_flush(cx),
         }
     }
 
@@ -46,6 +141,7 @@ impl AsyncWrite for Connection {
         match self.project() {
             ConnectionProjection::Tcp(s) => s.poll_shutdown(cx),
             ConnectionProjection::Unix(s) => s.poll_shutdown(cx),
+            ConnectionProjection::Custom(s) => s.poll_shutdown(cx),
         }
     }
 }
@@ -55,6 +151,7 @@ impl AsyncBufRead for Connection {
         match self.project() {
             ConnectionProjection::Tcp(s) => s.poll_fill_buf(cx),
             ConnectionProjection::Unix(s) => s.poll_fill_buf(cx),
+            ConnectionProjection::Custom(s) => s.poll_fill_buf(cx),
         }
     }
 
@@ -62,6 +159,7 @@ impl AsyncBufRead for Connection {
         match self.project() {
             ConnectionProjection::Tcp(s) => s.consume(amt),
             ConnectionProjection::Unix(s) => s.consume(amt),
+            ConnectionProjection::Custom(s) => s.consume(amt),
         }
     }
 }
@@ -96,13 +194,17 @@ impl Addr {
                 })?,
                 url.port().unwrap_or(Self::DEFAULT_PORT)
             ))),
+            "udp" => Err(Error::UnsupportedScheme {
+                scheme: url.scheme().to_string(),
+            }),
             _ => Ok(Addr::Unknown(dsn.to_string())),
         }
     }
 }
 
 impl Connection {
-    pub async fn new<S: AsRef<str>>(dsn: S) -> Result<Self, Error> {
+    /// Connects to the given DSN, setting `TCP_NODELAY` on TCP connections if `nodelay` is set.
+    pub async fn new<S: AsRef<str>>(dsn: S, nodelay: bool) -> Result<Self, Error> {
         match Addr::parse(dsn.as_ref())? {
             Addr::Unix(path) => UnixStream::connect(path)
                 .await
@@ -110,25 +212,128 @@ impl Connection {
                 .map_err(Error::Connect),
             Addr::Tcp(url) | Addr::Unknown(url) => {
                 let addrs = lookup_host(url).await.map_err(Error::Connect)?;
+                Self::connect_tcp(addrs, nodelay).await
+            }
+        }
+    }
+
+    pub fn from_stream(stream: Box<dyn AsyncStream>) -> Self {
+        Connection::Custom(BufReader::new(BufWriter::new(stream)))
+    }
+
+    /// Unwraps the underlying stream, along with any bytes which were read from it but not yet
+    /// consumed.
+    ///
+    /// Any writes which have not been flushed are discarded.
+    pub fn into_stream(self) -> (Box<dyn AsyncStream>, Vec<u8>) {
+        match self {
+            Connection::Tcp(s) => {
+                let buffered = s.buffer().to_vec();
+                (Box::new(s.into_inner().into_inner()), buffered)
+            }
+            Connection::Unix(s) => {
+                let buffered = s.buffer().to_vec();
+                (Box::new(s.into_inner().into_inner()), buffered)
+            }
+            Connection::Custom(s) => {
+                let buffered = s.buffer().to_vec();
+                (s.into_inner().into_inner(), buffered)
+            }
+        }
+    }
+
+    /// Returns the addresses of the underlying socket, and whether it is still connected.
+    pub fn info(&self) -> ConnectionInfo {
+        match self {
+            Connection::Tcp(s) => {
+                let stream = s.get_ref().get_ref();
+                let peer_addr = stream.peer_addr().ok();
+                ConnectionInfo {
+                    local_addr: stream.local_addr().ok(),
+                    peer_addr,
+                    socket_path: None,
+                    connected: peer_addr.is_some(),
+                }
+            }
+            Connection::Unix(s) => {
+                let peer_addr = s.get_ref().get_ref().peer_addr().ok();
+                ConnectionInfo {
+                    local_addr: None,
+                    peer_addr: None,
+                    socket_path: peer_addr
+                        .as_ref()
+                        .and_then(|a| a.as_pathname())
+                        .map(|p| p.to_path_buf()),
+                    connected: peer_addr.is_some(),
+                }
+            }
+            Connection::Custom(_) => ConnectionInfo {
+                connected: true,
+                ..Default::default()
+            },
+        }
+    }
 
-                let mut last_err = None;
+    /// Connects to each of the given addresses in turn, returning the first successful connection.
+    pub async fn connect_tcp<I: IntoIterator<Item = SocketAddr>>(
+        addrs: I,
+        nodelay: bool,
+    ) -> Result<Self, Error> {
+        let mut last_err = None;
 
-                for addr in addrs {
-                    let socket = TcpSocket::new_v4().map_err(Error::Connect)?;
-                    socket.set_nodelay(true).map_err(Error::Connect)?;
-                    match socket.connect(addr).await {
-                        Ok(stream) => {
-                            return Ok(Connection::Tcp(BufReader::new(BufWriter::new(stream))))
-                        }
-                        Err(e) => last_err = Some(Error::Connect(e)),
+        for addr in addrs {
+            let socket = match addr {
+                SocketAddr::V4(_) => TcpSocket::new_v4(),
+                SocketAddr::V6(_) => TcpSocket::new_v6(),
+            }
+            .map_err(Error::Connect)?;
+            socket.set_nodelay(nodelay).map_err(Error::Connect)?;
+            match socket.connect(addr).await {
+                Ok(stream) => return Ok(Connection::Tcp(BufReader::new(BufWriter::new(stream)))),
+                Err(e) => last_err = Some(Error::Connect(e)),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            Error::Connect(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any address",
+            ))
+        }))
+    }
+
+    /// Connects to the given DSN through a SOCKS5 proxy.
+    ///
+    /// The target address is resolved by the proxy, not locally, so hostnames only reachable from
+    /// the proxy's side of the network can be used.
+    #[cfg(feature = "socks")]
+    pub async fn new_with_proxy<S: AsRef<str>>(
+        dsn: S,
+        proxy: &ProxyConfig,
+        nodelay: bool,
+    ) -> Result<Self, Error> {
+        match Addr::parse(dsn.as_ref())? {
+            Addr::Unix(_) => Err(Error::Connect(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SOCKS5 proxies are only supported for TCP connections",
+            ))),
+            Addr::Tcp(target) | Addr::Unknown(target) => {
+                let stream = match proxy.credentials {
+                    Some((ref username, ref password)) => {
+                        Socks5Stream::connect_with_password(
+                            proxy.addr.as_str(),
+                            target,
+                            username,
+                            password,
+                        )
+                        .await
                     }
+                    None => Socks5Stream::connect(proxy.addr.as_str(), target).await,
                 }
-                Err(last_err.unwrap_or_else(|| {
-                    Error::Connect(io::Error::new(
-                        io::ErrorKind::InvalidInput,
-                        "could not resolve to any address",
-                    ))
-                }))
+                .map_err(|e| Error::Connect(io::Error::other(e)))?
+                .into_inner();
+
+                stream.set_nodelay(nodelay).map_err(Error::Connect)?;
+                Ok(Connection::Tcp(BufReader::new(BufWriter::new(stream))))
             }
         }
     }
@@ -169,4 +374,60 @@ mod tests {
             Error::Connect(_)
         ));
     }
+
+    #[tokio::test]
+    async fn test_connect_tcp_tries_each_address() {
+        use super::Connection;
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let open = listener.local_addr().unwrap();
+
+        // Find a port with nothing listening on it, by binding and immediately closing a listener.
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        assert!(matches!(
+            Connection::connect_tcp(vec![closed], true).await,
+            Err(Error::Connect(_))
+        ));
+        let conn = Connection::connect_tcp(vec![closed, open], true).await;
+        assert!(matches!(conn, Ok(Connection::Tcp(_))));
+
+        let info = conn.unwrap().info();
+        assert_eq!(info.peer_addr, Some(open));
+        assert!(info.local_addr.is_some());
+        assert!(info.connected);
+        assert!(matches!(
+            Connection::connect_tcp(Vec::new(), true).await,
+            Err(Error::Connect(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_udp_scheme_unsupported() {
+        assert_eq!(
+            Addr::parse("udp://localhost:11211"),
+            Err(Error::UnsupportedScheme {
+                scheme: "udp".to_string(),
+            })
+        )
+    }
+
+    #[cfg(feature = "socks")]
+    #[tokio::test]
+    async fn test_proxy_rejects_unix_scheme() {
+        use super::{Connection, ProxyConfig};
+
+        let proxy = ProxyConfig::new("127.0.0.1:1080");
+        assert!(matches!(
+            Connection::new_with_proxy("unix:///tmp/memcached.sock", &proxy, true)
+                .await
+                .unwrap_err(),
+            Error::Connect(_)
+        ));
+    }
 }
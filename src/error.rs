@@ -1,25 +1,157 @@
-use crate::parser::Status;
-use std::{fmt, io};
+use crate::parser::{ErrorKind, Status};
+use std::{fmt, io, time::Duration};
 
 /// Error type for [`Client`](crate::Client) operations.
+///
+/// Errors are split between failures of the connection itself, which usually poison the client,
+/// and commands which the server processed but did not apply, which are returned as
+/// [`Error::Command`].
 #[derive(Debug)]
 pub enum Error {
     /// Connect error.
     /// Useful for distinguishing between transitive I/O errors and connection errors.
     Connect(io::Error),
-    /// I/O-related error.
-    Io(io::Error),
-    /// A protocol-level error i.e. a failed operation or message that
-    /// does not match the protocol specification.
-    Protocol(Status),
+    /// The connection failed while a command was being sent or its response was being read.
+    Transport(io::Error),
+    /// The server sent a response which could not be parsed or does not belong to the command,
+    /// or could not parse the framing of the command
+    /// ([`ErrorKind::BadCommandFormat`]).
+    Protocol(ErrorKind),
+    /// The connection is no longer aligned with the protocol, e.g. because the server rejected a
+    /// value whose length did not match the length declared for it.
+    ///
+    /// The client is poisoned when this is returned, and a new one must be created.
+    ProtocolDesync(String),
+    /// An operation did not complete within its timeout.
+    Timeout {
+        /// Name of the operation which timed out, e.g. `metadump`.
+        operation: &'static str,
+        /// Server the operation was sent to, as an address or socket path.
+        endpoint: String,
+        /// How long the operation was given before it timed out.
+        after: Duration,
+    },
+    /// The server processed the command but did not apply it, or rejected it.  The connection can
+    /// still be used.
+    Command(CommandError),
+    /// An error returned by a command which was tagged with a correlation ID, through
+    /// [`WithContext::with_context`].
+    Context {
+        /// Correlation ID of the command, e.g. the ID of the application request that issued it.
+        id: String,
+        /// The error returned by the command.
+        source: Box<Error>,
+    },
+    /// The scheme of the given DSN is recognized, but not supported by this client.
+    UnsupportedScheme {
+        /// Scheme of the DSN.
+        scheme: String,
+    },
+}
+
+/// A command which the server processed but did not apply, or rejected, as returned in
+/// [`Error::Command`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommandError {
+    /// The value was not stored, e.g. because `add` found the key already present (`NOT_STORED`).
+    NotStored,
+    /// The key was changed since its CAS identifier was read (`EXISTS`).
+    Exists,
+    /// The key was not found (`NOT_FOUND`).
+    NotFound,
+    /// The command was rejected, either by the server (`ERROR`, `CLIENT_ERROR` or
+    /// `SERVER_ERROR`) or by this crate before it was sent.
+    Error(ErrorKind),
+    /// The server rejected a value as too large to store, with the given message.
+    ///
+    /// For [`Client::try_append`](crate::Client::try_append) and
+    /// [`Client::try_prepend`](crate::Client::try_prepend), this means the combined value would
+    /// exceed the server's maximum item size.
+    ValueTooLarge(String),
+    /// The flags of a key did not match the flags it was expected to have, as checked by
+    /// [`Client::set_if_flags`](crate::Client::set_if_flags).
+    FlagsMismatch {
+        /// Flags the key was expected to have.
+        expected: u32,
+        /// Flags the key actually had.
+        actual: u32,
+    },
+}
+
+/// Whether an [`Error`] came from the connection or from the server rejecting a command.
+///
+/// This is meant to drive decisions such as whether to retry a command or discard a client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// The connection failed, timed out, or can no longer be trusted to be aligned with the
+    /// protocol.
+    ///
+    /// The command may succeed if retried, usually on a new connection, and the client should
+    /// generally be replaced, as it is poisoned by many of these errors.
+    Transport,
+    /// The server processed the command but did not apply it, e.g. because the key was not found
+    /// or a CAS identifier did not match, or rejected it with a client or server error.
+    ///
+    /// The connection is still fine, and retrying the same command will usually fail the same way.
+    Command,
+}
+
+impl Error {
+    /// Returns whether this error came from the connection or from the server rejecting a
+    /// command.
+    ///
+    /// [`Error::Command`] is a command error, [`Error::Context`] has the category of the error
+    /// it wraps, and every other variant is a transport error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Command(_) => ErrorCategory::Command,
+            Self::Context { source, .. } => source.category(),
+            Self::Connect(_)
+            | Self::Transport(_)
+            | Self::Protocol(_)
+            | Self::ProtocolDesync(_)
+            | Self::Timeout { .. }
+            | Self::UnsupportedScheme { .. } => ErrorCategory::Transport,
+        }
+    }
+
+    /// Returns whether this is a [transport](ErrorCategory::Transport) error.
+    pub fn is_transport(&self) -> bool {
+        self.category() == ErrorCategory::Transport
+    }
+
+    /// Returns whether this is a [command](ErrorCategory::Command) error.
+    pub fn is_command(&self) -> bool {
+        self.category() == ErrorCategory::Command
+    }
 }
 
 impl PartialEq for Error {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (Self::Connect(e1), Self::Connect(e2)) => e1.kind() == e2.kind(),
-            (Self::Io(e1), Self::Io(e2)) => e1.kind() == e2.kind(),
-            (Self::Protocol(s1), Self::Protocol(s2)) => s1 == s2,
+            (Self::Transport(e1), Self::Transport(e2)) => e1.kind() == e2.kind(),
+            (Self::Protocol(k1), Self::Protocol(k2)) => k1 == k2,
+            (Self::ProtocolDesync(s1), Self::ProtocolDesync(s2)) => s1 == s2,
+            (
+                Self::Timeout {
+                    operation: o1,
+                    endpoint: e1,
+                    after: a1,
+                },
+                Self::Timeout {
+                    operation: o2,
+                    endpoint: e2,
+                    after: a2,
+                },
+            ) => o1 == o2 && e1 == e2 && a1 == a2,
+            (Self::Command(c1), Self::Command(c2)) => c1 == c2,
+            (Self::Context { id: i1, source: s1 }, Self::Context { id: i2, source: s2 }) => {
+                i1 == i2 && s1 == s2
+            }
+            (Self::UnsupportedScheme { scheme: s1 }, Self::UnsupportedScheme { scheme: s2 }) => {
+                s1 == s2
+            }
             _ => false,
         }
     }
@@ -28,30 +160,201 @@ impl PartialEq for Error {
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
-            Self::Io(ref e) => Some(e),
+            Self::Transport(ref e) => Some(e),
+            Self::Context { ref source, .. } => Some(source.as_ref()),
             _ => None,
         }
     }
 }
 
+impl std::error::Error for CommandError {}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::Connect(e) => write!(f, "connect: {}", e),
-            Self::Io(e) => write!(f, "io: {}", e),
+            Self::Transport(e) => write!(f, "transport: {}", e),
             Self::Protocol(e) => write!(f, "protocol: {}", e),
+            Self::ProtocolDesync(e) => write!(f, "protocol desync: {}", e),
+            Self::Timeout {
+                operation,
+                endpoint,
+                after,
+            } => write!(
+                f,
+                "{} to {} timed out after {:?}",
+                operation, endpoint, after
+            ),
+            Self::Command(e) => write!(f, "command: {}", e),
+            Self::Context { id, source } => write!(f, "[{}] {}", id, source),
+            Self::UnsupportedScheme { scheme } => write!(f, "unsupported scheme `{}`", scheme),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotStored => write!(f, "not stored"),
+            Self::Exists => write!(f, "exists"),
+            Self::NotFound => write!(f, "not found"),
+            Self::Error(ek) => write!(f, "error: {}", ek),
+            Self::ValueTooLarge(e) => write!(f, "value too large: {}", e),
+            Self::FlagsMismatch { expected, actual } => {
+                write!(f, "flags mismatch: expected {}, got {}", expected, actual)
+            }
         }
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Error::Io(e)
+        Error::Transport(e)
+    }
+}
+
+impl From<CommandError> for Error {
+    fn from(e: CommandError) -> Self {
+        Error::Command(e)
     }
 }
 
 impl From<Status> for Error {
+    /// Converts a status which ended a command into an error.
+    ///
+    /// Responses which could not be parsed and [`ErrorKind::BadCommandFormat`] become
+    /// [`Error::Protocol`], as do statuses which only signal success, since they cannot be the
+    /// response to a command which failed.  Every other status becomes an [`Error::Command`].
     fn from(s: Status) -> Self {
-        Error::Protocol(s)
+        match s {
+            Status::NotStored => CommandError::NotStored.into(),
+            Status::Exists => CommandError::Exists.into(),
+            Status::NotFound => CommandError::NotFound.into(),
+            Status::Error(kind @ ErrorKind::Protocol(_))
+            | Status::Error(kind @ ErrorKind::BadCommandFormat) => Error::Protocol(kind),
+            Status::Error(kind) => CommandError::Error(kind).into(),
+            Status::Stored | Status::Deleted | Status::Touched => Error::Protocol(
+                ErrorKind::Protocol(Some(format!("unexpected status: {}", s))),
+            ),
+        }
+    }
+}
+
+/// Tags the error of a command with a correlation ID.
+///
+/// This allows a cache failure to be tied back to the application request which caused it, e.g.
+/// `client.get(key).await.with_context(request_id)`.  With the `tracing` feature, a `WARN` event
+/// carrying the correlation ID and the error is also emitted, within whichever span is current, so
+/// the failure shows up in the trace of that request.
+pub trait WithContext {
+    /// Wraps any error in [`Error::Context`] with the given correlation ID.
+    fn with_context(self, id: &str) -> Self;
+}
+
+impl<T> WithContext for Result<T, Error> {
+    fn with_context(self, id: &str) -> Self {
+        self.map_err(|e| {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(correlation_id = %id, error = %e, "memcached command failed");
+
+            Error::Context {
+                id: id.to_string(),
+                source: Box::new(e),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CommandError, Error, ErrorCategory};
+    use crate::parser::{ErrorKind, Status};
+    use std::{io, time::Duration};
+
+    #[test]
+    fn test_from_status() {
+        let cases = vec![
+            (Status::NotStored, Error::Command(CommandError::NotStored)),
+            (Status::Exists, Error::Command(CommandError::Exists)),
+            (Status::NotFound, Error::Command(CommandError::NotFound)),
+            (
+                Status::Error(ErrorKind::Server("out of memory".to_string())),
+                Error::Command(CommandError::Error(ErrorKind::Server(
+                    "out of memory".to_string(),
+                ))),
+            ),
+            (
+                Status::Error(ErrorKind::NonexistentCommand),
+                Error::Command(CommandError::Error(ErrorKind::NonexistentCommand)),
+            ),
+            (
+                Status::Error(ErrorKind::Protocol(None)),
+                Error::Protocol(ErrorKind::Protocol(None)),
+            ),
+            (
+                Status::Error(ErrorKind::BadCommandFormat),
+                Error::Protocol(ErrorKind::BadCommandFormat),
+            ),
+            (
+                Status::Stored,
+                Error::Protocol(ErrorKind::Protocol(Some(
+                    "unexpected status: stored".to_string(),
+                ))),
+            ),
+        ];
+        for (status, expected) in cases {
+            assert_eq!(Error::from(status), expected);
+        }
+
+        assert_eq!(
+            Error::from(io::Error::from(io::ErrorKind::BrokenPipe)),
+            Error::Transport(io::ErrorKind::BrokenPipe.into())
+        );
+    }
+
+    #[test]
+    fn test_category() {
+        let transport = vec![
+            Error::Connect(io::ErrorKind::ConnectionRefused.into()),
+            Error::Transport(io::ErrorKind::UnexpectedEof.into()),
+            Error::Protocol(ErrorKind::Protocol(None)),
+            Error::Protocol(ErrorKind::BadCommandFormat),
+            Error::ProtocolDesync("bad data chunk".to_string()),
+            Error::Timeout {
+                operation: "metadump",
+                endpoint: "127.0.0.1:11211".to_string(),
+                after: Duration::from_secs(1),
+            },
+            Error::UnsupportedScheme {
+                scheme: "udp".to_string(),
+            },
+        ];
+        for error in transport {
+            assert_eq!(error.category(), ErrorCategory::Transport, "{:?}", error);
+            assert!(error.is_transport() && !error.is_command());
+        }
+
+        let command = vec![
+            CommandError::NotFound,
+            CommandError::NotStored,
+            CommandError::Exists,
+            CommandError::Error(ErrorKind::Generic("unsupported flags".to_string())),
+            CommandError::Error(ErrorKind::Client("bad data".to_string())),
+            CommandError::ValueTooLarge("object too large for cache".to_string()),
+            CommandError::FlagsMismatch {
+                expected: 1,
+                actual: 2,
+            },
+        ];
+        for error in command.into_iter().map(Error::from) {
+            assert_eq!(error.category(), ErrorCategory::Command, "{:?}", error);
+            assert!(error.is_command() && !error.is_transport());
+        }
+
+        let tagged = Error::Context {
+            id: "request-1".to_string(),
+            source: Box::new(Error::Transport(io::ErrorKind::BrokenPipe.into())),
+        };
+        assert_eq!(tagged.category(), ErrorCategory::Transport);
     }
 }
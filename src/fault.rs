@@ -0,0 +1,238 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Sleep;
+
+/// A fault injected into a command by a [`FaultInjector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// Delays sending the command by the given duration.
+    Delay(Duration),
+    /// Discards the command without sending it, so no response to it ever arrives.
+    Drop,
+    /// Discards the command without sending it, and fails the next read, i.e. that of its
+    /// response, with an I/O error of the given kind.  As with a real failed read, this poisons
+    /// the client.
+    Error(io::ErrorKind),
+}
+
+impl Fault {
+    /// Combines two faults injected into the same write, with errors taking precedence over drops,
+    /// and drops over delays.
+    fn combine(self, other: Fault) -> Fault {
+        match (self, other) {
+            (Fault::Delay(a), Fault::Delay(b)) => Fault::Delay(a + b),
+            (Fault::Error(kind), _) | (_, Fault::Error(kind)) => Fault::Error(kind),
+            (Fault::Drop, _) | (_, Fault::Drop) => Fault::Drop,
+        }
+    }
+}
+
+struct Rule {
+    command: Vec<u8>,
+    seen: u64,
+    predicate: Box<dyn FnMut(u64) -> bool + Send>,
+    fault: Fault,
+}
+
+/// Stream middleware which deterministically injects faults into the commands sent through it.
+///
+/// This is meant for testing how an application handles cache failures, without a broken
+/// server.  It wraps the stream of a client through
+/// [`Client::wrap_stream`](crate::Client::wrap_stream), and faults are configured per command with
+/// [`FaultInjector::on`], e.g. to fail every third `set` or to delay every `get`:
+///
+/// ```no_run
+/// # async fn example() -> Result<(), async_memcached::Error> {
+/// use async_memcached::{Client, Fault, FaultInjector};
+/// use std::{io::ErrorKind, time::Duration};
+///
+/// let client = Client::new("tcp://127.0.0.1:11211").await?;
+/// let client = client
+///     .wrap_stream(|stream| {
+///         FaultInjector::new(stream)
+///             .on("set", |n| n % 3 == 0, Fault::Error(ErrorKind::ConnectionReset))
+///             .on("get", |_| true, Fault::Delay(Duration::from_millis(100)))
+///     })
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Faults apply to a whole write to the stream.  As a client flushes each command as it is sent,
+/// this is normally a single command, but pipelined commands, such as those of
+/// [`Client::set_multi`](crate::Client::set_multi), are all faulted together.
+///
+/// Only available with the `testing` feature.
+pub struct FaultInjector<S> {
+    inner: S,
+    rules: Vec<Rule>,
+    // Partial command line, and the length of the data block which follows the last command.
+    line: Vec<u8>,
+    skip: usize,
+    // Length of the current write which has been scanned for commands, and the fault to inject.
+    scanned: usize,
+    fault: Option<Fault>,
+    delay: Option<Pin<Box<Sleep>>>,
+    read_error: Option<io::ErrorKind>,
+}
+
+impl<S> FaultInjector<S> {
+    /// Wraps the given stream, without injecting any faults yet.
+    pub fn new(inner: S) -> FaultInjector<S> {
+        FaultInjector {
+            inner,
+            rules: Vec::new(),
+            line: Vec::new(),
+            skip: 0,
+            scanned: 0,
+            fault: None,
+            delay: None,
+            read_error: None,
+        }
+    }
+
+    /// Injects `fault` into the commands named `command`, e.g. `"set"` or `"mg"`, for which
+    /// `predicate` returns `true`.
+    ///
+    /// `predicate` is given the number of times the command has been sent, starting from 1, so
+    /// that faults can be injected deterministically.  If several faults apply to the same write,
+    /// delays are added up, and errors take precedence over drops, and drops over delays.
+    pub fn on<P>(mut self, command: &str, predicate: P, fault: Fault) -> FaultInjector<S>
+    where
+        P: FnMut(u64) -> bool + Send + 'static,
+    {
+        self.rules.push(Rule {
+            command: command.as_bytes().to_vec(),
+            seen: 0,
+            predicate: Box::new(predicate),
+            fault,
+        });
+        self
+    }
+
+    /// Finds the commands in the given bytes, returning the fault to inject into them, if any.
+    fn scan(&mut self, mut bytes: &[u8]) -> Option<Fault> {
+        let mut fault: Option<Fault> = None;
+
+        while !bytes.is_empty() {
+            if self.skip > 0 {
+                let n = self.skip.min(bytes.len());
+                self.skip -= n;
+                bytes = &bytes[n..];
+                continue;
+            }
+
+            let end = match bytes.iter().position(|b| *b == b'\n') {
+                Some(i) => i + 1,
+                None => {
+                    self.line.extend_from_slice(bytes);
+                    break;
+                }
+            };
+            self.line.extend_from_slice(&bytes[..end]);
+            bytes = &bytes[end..];
+
+            let line = std::mem::take(&mut self.line);
+            self.skip = data_block_len(&line);
+            let name = line
+                .split(|b| *b == b' ' || *b == b'\r' || *b == b'\n')
+                .next()
+                .unwrap_or_default();
+            for rule in self.rules.iter_mut().filter(|rule| rule.command == name) {
+                rule.seen += 1;
+                if (rule.predicate)(rule.seen) {
+                    fault = Some(match fault {
+                        Some(f) => f.combine(rule.fault),
+                        None => rule.fault,
+                    });
+                }
+            }
+        }
+
+        fault
+    }
+}
+
+/// Length of the data block, including its trailing `\r\n`, which follows the given command line.
+fn data_block_len(line: &[u8]) -> usize {
+    let line = line.strip_suffix(b"\r\n").unwrap_or(line);
+    let parts: Vec<&[u8]> = line.split(|b| *b == b' ').collect();
+    let len = match parts[0] {
+        b"set" | b"add" | b"replace" | b"append" | b"prepend" | b"cas" => parts.get(4),
+        b"ms" => parts.get(2),
+        _ => None,
+    };
+    len.and_then(|len| btoi::btou::<usize>(len).ok())
+        .map_or(0, |len| len + 2)
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultInjector<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(kind) = self.read_error.take() {
+            return Poll::Ready(Err(io::Error::new(kind, "injected fault")));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FaultInjector<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+
+        // A write which was only partly accepted is retried with the rest of its bytes, which
+        // have already been scanned, so only scan any bytes beyond those.
+        if buf.len() > this.scanned {
+            if let Some(fault) = this.scan(&buf[this.scanned..]) {
+                if let Fault::Delay(after) = fault {
+                    this.delay = Some(Box::pin(tokio::time::sleep(after)));
+                }
+                this.fault = Some(match this.fault {
+                    Some(f) => f.combine(fault),
+                    None => fault,
+                });
+            }
+            this.scanned = buf.len();
+        }
+
+        if let Some(delay) = &mut this.delay {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+
+        let n = match this.fault {
+            Some(Fault::Drop) => buf.len(),
+            Some(Fault::Error(kind)) => {
+                this.read_error = Some(kind);
+                buf.len()
+            }
+            _ => ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?,
+        };
+
+        this.scanned -= n.min(this.scanned);
+        if this.scanned == 0 {
+            this.fault = None;
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
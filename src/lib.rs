@@ -2,20 +2,66 @@
 #![deny(warnings, missing_docs)]
 
 use bytes::BytesMut;
-use fxhash::FxHashMap;
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use fxhash::FxHashSet;
+use std::collections::HashMap;
+
+/// Map returned by commands with a result for each key, such as [`Client::set_multi`].
+///
+/// This is re-exported so that these results can be named without depending on `fxhash`.
+pub use fxhash::FxHashMap;
+use std::io::IoSlice;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+mod builder;
+pub use self::builder::ClientBuilder;
 
 mod connection;
 use self::connection::Connection;
+#[cfg(feature = "socks")]
+pub use self::connection::ProxyConfig;
+pub use self::connection::{AsyncStream, ConnectionInfo};
 
 mod error;
-pub use self::error::Error;
+pub use self::error::{CommandError, Error, ErrorCategory, WithContext};
+
+#[cfg(feature = "testing")]
+mod fault;
+#[cfg(feature = "testing")]
+pub use self::fault::{Fault, FaultInjector};
+
+#[cfg(any(feature = "bb8", feature = "deadpool"))]
+mod manager;
+#[cfg(any(feature = "bb8", feature = "deadpool"))]
+pub use self::manager::ClientManager;
+
+mod options;
+pub use self::options::ClientOptions;
 
 mod parser;
 use self::parser::{
-    parse_ascii_metadump_response, parse_ascii_response, parse_ascii_stats_response, Response,
+    parse_ascii_line, parse_ascii_meta_response, parse_ascii_metadump_response,
+    parse_ascii_response, parse_ascii_stats_response, parse_ascii_value_header,
+    parse_ascii_value_response, parse_ascii_value_trailer, MetaResponse, Response,
+    ValueHeaderResponse, ValueResponse,
+};
+pub use self::parser::{
+    BytesValue, ErrorKind, KeyMetadata, MetadumpResponse, StatsResponse, Status, Value,
+};
+
+mod limits;
+pub use self::limits::Limits;
+
+mod meta;
+pub use self::meta::{
+    ArithmeticFlags, ArithmeticMode, CounterValue, DeleteFlags, GetFlags, MetaValue, RecacheState,
+    StoreFlags, StoreMode,
 };
-pub use self::parser::{ErrorKind, KeyMetadata, MetadumpResponse, StatsResponse, Status, Value};
+
+mod stats;
+pub use self::stats::{StatsBundle, StatsSummary};
 
 mod value_serializer;
 pub use self::value_serializer::AsMemcachedValue;
@@ -28,52 +74,472 @@ pub struct Client {
     buf: BytesMut,
     last_read_n: Option<usize>,
     conn: Connection,
+    server_time: Option<(Instant, SystemTime)>,
+    key_buf: Vec<u8>,
+    poisoned: bool,
+    meta_supported: Option<bool>,
+    limits: Option<Limits>,
+    read_chunk_size: usize,
+    avg_response_size: usize,
+    options: ClientOptions,
+    get_hits: u64,
+    get_misses: u64,
+    shrink_buf: bool,
+    last_response: Instant,
 }
 
+/// How long a server time retrieved by [`Client::server_time`] is reused before `stats` is queried
+/// again.
+const SERVER_TIME_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Number of keys requested by each command written by [`Client::get_multi_pipelined`].
+const PIPELINED_GET_CHUNK_SIZE: usize = 100;
+
+/// Number of `delete` commands written by [`Client::delete_prefix`] before their responses are
+/// read.
+const PIPELINED_DELETE_WINDOW: usize = 100;
+
+/// Smallest number of bytes reserved in the read buffer before each read from the connection,
+/// unless a larger [`ClientOptions::read_buffer_capacity`] is set.
+const MIN_READ_CHUNK_SIZE: usize = 1024;
+
+/// Largest number of bytes reserved in the read buffer before each read from the connection.
+const MAX_READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How long [`Client::dump_keys_retry`] waits before retrying a metadump when the LRU crawler is
+/// busy.
+const METADUMP_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// TTL of the key stored by [`Client::self_test`], so it expires if it could not be deleted.
+const SELF_TEST_TTL: i64 = 60;
+
+/// Number of keys stored by [`Client::self_test`] in this process, to keep each key unique.
+static SELF_TEST_COUNTER: AtomicU64 = AtomicU64::new(0);
+
 impl Client {
     /// Creates a new [`Client`] based on the given data source string.
     ///
     /// Supports UNIX domain sockets and TCP connections.
     /// For TCP: the DSN should be in the format of `tcp://<IP>:<port>` or `<IP>:<port>`.
     /// For UNIX: the DSN should be in the format of `unix://<path>`.
+    ///
+    /// To configure the connection, such as with a connect timeout, use [`Client::builder`].
     pub async fn new<S: AsRef<str>>(dsn: S) -> Result<Client, Error> {
-        let connection = Connection::new(dsn).await?;
+        ClientBuilder::new().connect(dsn).await
+    }
+
+    /// Returns a [`ClientBuilder`], to configure a connection before making it.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::new()
+    }
+
+    /// Creates a new [`Client`] connected over TCP to the first of the given addresses which
+    /// accepts a connection.
+    ///
+    /// When addresses have already been resolved, e.g. by service discovery, this should be
+    /// preferred over [`Client::new`], as it avoids formatting them into a DSN only for it to be
+    /// parsed and resolved again, along with the bracketing required for IPv6 addresses in a DSN.
+    /// To connect to a single address, pass `&[addr]`.
+    ///
+    /// If no connection could be made, the error from the last address tried is returned.
+    pub async fn connect_tcp(addrs: &[SocketAddr]) -> Result<Client, Error> {
+        let connection = Connection::connect_tcp(addrs.iter().copied(), true).await?;
+
+        Ok(Client::from_connection(connection))
+    }
+
+    /// Creates a new [`Client`] which communicates over the given stream.
+    ///
+    /// This allows any transport implementing [`AsyncStream`] to be used, such as an in-memory
+    /// stream in tests, or a connection established by other means.
+    pub fn from_stream<S: AsyncStream + 'static>(stream: S) -> Client {
+        Client::from_connection(Connection::from_stream(Box::new(stream)))
+    }
+
+    /// Creates a new [`Client`] based on the given data source string, optionally tunneling the
+    /// connection through a SOCKS5 proxy.
+    ///
+    /// Only TCP DSNs can be proxied; UNIX domain socket DSNs will return [`Error::Connect`] if a
+    /// proxy is given.  If `proxy` is `None`, this behaves exactly like [`Client::new`].
+    ///
+    /// This is equivalent to connecting with a [`ClientBuilder`] given the proxy with
+    /// [`ClientBuilder::proxy`], which should be used to configure the connection further.
+    #[cfg(feature = "socks")]
+    pub async fn new_with_proxy<S: AsRef<str>>(
+        dsn: S,
+        proxy: Option<ProxyConfig>,
+    ) -> Result<Client, Error> {
+        let builder = ClientBuilder::new();
+        match proxy {
+            Some(proxy) => builder.proxy(proxy).connect(dsn).await,
+            None => builder.connect(dsn).await,
+        }
+    }
 
-        Ok(Client {
+    fn from_connection(conn: Connection) -> Client {
+        Client {
             buf: BytesMut::new(),
             last_read_n: None,
-            conn: connection,
-        })
+            conn,
+            server_time: None,
+            key_buf: Vec::new(),
+            poisoned: false,
+            meta_supported: None,
+            limits: None,
+            read_chunk_size: MIN_READ_CHUNK_SIZE,
+            avg_response_size: 0,
+            options: ClientOptions::default(),
+            get_hits: 0,
+            get_misses: 0,
+            shrink_buf: false,
+            last_response: Instant::now(),
+        }
+    }
+
+    /// Sets the TTL, in seconds, used by storage commands when none is given.
+    ///
+    /// Once set, passing `None` as the `ttl` of [`Client::set`], [`Client::add`] and the other
+    /// storage commands uses this TTL rather than the protocol default of 0, while a TTL passed to
+    /// a command always takes precedence.  To store a key which never expires, pass `Some(0)`
+    /// explicitly.
+    pub fn with_default_ttl(mut self, ttl: i64) -> Client {
+        self.options.default_ttl = Some(ttl);
+        self
+    }
+
+    /// Sets the client flags used by storage commands when none are given.
+    ///
+    /// As with [`Client::with_default_ttl`], flags passed to a command always take precedence, and
+    /// `Some(0)` must be passed explicitly to store a key without flags.
+    pub fn with_default_flags(mut self, flags: u32) -> Client {
+        self.options.default_flags = Some(flags);
+        self
+    }
+
+    /// Limits the length of a single line of a response, such as a `VALUE` header or a `STAT`.
+    ///
+    /// If the server sends more than `max` bytes without ending the line, the response is treated
+    /// as malformed rather than waiting for the rest of it, and [`ErrorKind::Protocol`] is
+    /// returned with a message of `line too long`.  As the rest of the line is left unread, the
+    /// client is poisoned.  This guards against misbehaving servers, and does not limit the size
+    /// of values, whose data is not a line.  By default, lines are not limited.
+    pub fn with_max_line_bytes(mut self, max: usize) -> Client {
+        self.options.max_line_bytes = Some(max);
+        self
+    }
+
+    /// Limits the length of a single `get` command line sent by [`Client::get_multi`], including
+    /// its trailing `\r\n`.
+    ///
+    /// Servers and proxies may cap the length of a command line, which a `get` for thousands of
+    /// long keys can exceed.  Once set, the keys are split across as many `get` commands as
+    /// needed to keep each one within `max` bytes, which are pipelined and whose values are
+    /// merged into a single result.  A key too long to fit in `max` bytes on its own is still sent,
+    /// in a command of its own.  By default, command lines are not limited.
+    pub fn with_max_command_bytes(mut self, max: usize) -> Client {
+        self.options.max_command_bytes = Some(max);
+        self
+    }
+
+    /// Caps the memory retained by the read buffer after a response larger than `max` bytes.
+    ///
+    /// The read buffer grows to hold the largest response read, and otherwise keeps that memory
+    /// for the lifetime of the client, so a single outlier value can permanently inflate its
+    /// footprint.  Once set, a response larger than `max` bytes causes the buffer to be replaced
+    /// by a smaller one when the next response is read, and the buffer never grows beyond `max`
+    /// bytes in anticipation of future responses.  Large responses can still be read in full.
+    /// By default, the buffer is never shrunk.
+    pub fn set_read_buffer_high_water(&mut self, max: usize) {
+        self.options.read_buffer_high_water = Some(max);
+        self.read_chunk_size = self.read_chunk_size.min(max.max(MIN_READ_CHUNK_SIZE));
+    }
+
+    /// Replaces all of the options of this client.
+    ///
+    /// See [`ClientOptions`] for the defaults used by a new client.
+    pub fn with_options(mut self, options: ClientOptions) -> Client {
+        self.options = options;
+        self
+    }
+
+    /// Returns the options of this client.
+    pub fn options(&self) -> &ClientOptions {
+        &self.options
+    }
+
+    /// Resolves the TTL of a storage command, falling back to the client's default and then to 0.
+    fn storage_ttl(&self, ttl: Option<i64>) -> i64 {
+        ttl.or(self.options.default_ttl).unwrap_or(0)
+    }
+
+    /// Resolves the flags of a storage command, falling back to the client's default and then to 0.
+    fn storage_flags(&self, flags: Option<u32>) -> u32 {
+        flags.or(self.options.default_flags).unwrap_or(0)
+    }
+
+    /// Whether this client was poisoned by a command that was cancelled while reading its
+    /// response, or which left the connection out of sync with the protocol.
+    ///
+    /// Dropping a command's future before it completes, such as when it loses a `select!` or is
+    /// wrapped in a timeout, can leave the rest of its response unread on the connection.
+    /// Similarly, if the server rejects a value because its length did not match the declared
+    /// length ([`Error::ProtocolDesync`]), the rest of the value is read by the server as further
+    /// commands.  Any later command would then read the wrong response, so once poisoned, every
+    /// command that reads a response fails with an [`Error::Transport`] instead.  A poisoned client
+    /// should normally be replaced with a new one, though [`Client::drain`] can recover it once
+    /// nothing more is expected from the server.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Wraps the stream underlying this client with the stream returned by `wrap`.
+    ///
+    /// This allows middleware to be inserted between the client and the server, such as to log
+    /// or count the bytes exchanged, measure latency, or inject faults in tests.  The wrapper is
+    /// given the current stream, and all subsequent commands are sent through the stream it
+    /// returns.  Any pending writes are flushed before the stream is wrapped, and any bytes
+    /// already read from the server are kept, so no data is lost.
+    pub async fn wrap_stream<F, S>(mut self, wrap: F) -> Result<Client, Error>
+    where
+        F: FnOnce(Box<dyn AsyncStream>) -> S,
+        S: AsyncStream + 'static,
+    {
+        self.conn.flush().await?;
+
+        let (stream, buffered) = self.conn.into_stream();
+        self.buf.extend_from_slice(&buffered);
+        self.conn = Connection::from_stream(Box::new(wrap(stream)));
+
+        Ok(self)
+    }
+
+    /// Returns details of the connection to the server, such as its local and remote addresses.
+    ///
+    /// This is useful for logging which connection an error came from when many clients are in
+    /// use.  No command is sent to the server.
+    pub fn connection_info(&self) -> ConnectionInfo {
+        self.conn.info()
+    }
+
+    /// Builds an [`Error::Timeout`] for the given operation against this client's server.
+    fn timeout_error(&self, operation: &'static str, after: Duration) -> Error {
+        let info = self.conn.info();
+        let endpoint = match (info.peer_addr, info.socket_path) {
+            (Some(addr), _) => addr.to_string(),
+            (None, Some(path)) => path.display().to_string(),
+            (None, None) => "unknown endpoint".to_string(),
+        };
+        Error::Timeout {
+            operation,
+            endpoint,
+            after,
+        }
+    }
+
+    /// Whether bytes have been read from the server beyond the last parsed response.
+    ///
+    /// After a command completes, any such bytes were not requested by it, e.g. the remainder of
+    /// a response to a command that failed part way through.  A client with pending bytes should
+    /// not be reused for unrelated commands, as they would be read as the next command's response.
+    pub fn has_pending_bytes(&self) -> bool {
+        self.buf.len() > self.last_read_n.unwrap_or(0)
+    }
+
+    /// How long it has been since a response was last read on this connection, or since it was
+    /// made if no response has been read yet.
+    ///
+    /// The server closes connections which have been idle for longer than its `idle_timeout`, so a
+    /// client which has been idle for a while may find its connection gone on the next command.
+    pub fn idle_time(&self) -> Duration {
+        self.last_response.elapsed()
+    }
+
+    /// Discards any unread bytes, both those already buffered and those currently available from
+    /// the server, returning how many bytes were discarded.
+    ///
+    /// This doesn't wait for more data to arrive: it stops as soon as a read would block, so it
+    /// returns promptly if nothing is pending.  Afterwards, the client is no longer poisoned.  This
+    /// is a lighter way to recover than reconnecting, such as after a cancelled command or if a
+    /// proxy sends unsolicited data.
+    ///
+    /// Any response which is still in flight, and so is only partially discarded, is left to
+    /// corrupt the next command, so this should only be used when the connection is already known
+    /// to be in a bad state.
+    pub async fn drain(&mut self) -> Result<usize, Error> {
+        let mut discarded = self.buf.len() - self.last_read_n.take().unwrap_or(0);
+        self.buf.clear();
+
+        loop {
+            self.buf.reserve(self.read_chunk_size);
+            match tokio::time::timeout(Duration::ZERO, self.conn.read_buf(&mut self.buf)).await {
+                Ok(Ok(0)) | Err(_) => break,
+                Ok(Ok(n)) => {
+                    discarded += n;
+                    self.buf.clear();
+                }
+                Ok(Err(e)) => return Err(e.into()),
+            }
+        }
+
+        self.poisoned = false;
+        Ok(discarded)
+    }
+
+    /// Clears all metadata cached about the server, so that it is fetched again when next needed.
+    ///
+    /// This covers whether meta commands are supported, the limits returned by [`Client::limits`],
+    /// and the offset used by [`Client::server_time`].  These are not expected to change over the
+    /// lifetime of a connection, but can go stale if the server is upgraded or reconfigured behind
+    /// a proxy, in which case this avoids having to create a new client.
+    ///
+    /// The hit and miss counts returned by [`Client::get_hits`] and [`Client::get_misses`] are
+    /// reset as well.
+    pub fn reset_caches(&mut self) {
+        self.server_time = None;
+        self.meta_supported = None;
+        self.limits = None;
+        self.get_hits = 0;
+        self.get_misses = 0;
+    }
+
+    /// Number of keys found by retrieval commands sent through this client.
+    ///
+    /// Keys are counted by [`Client::get`], [`Client::get_multi`] and the other `get` methods, for
+    /// this client's connection only, since it was created or [`Client::reset_caches`] was called.
+    pub fn get_hits(&self) -> u64 {
+        self.get_hits
+    }
+
+    /// Number of keys not found by retrieval commands sent through this client.
+    ///
+    /// Keys are counted in the same way as for [`Client::get_hits`].
+    pub fn get_misses(&self) -> u64 {
+        self.get_misses
+    }
+
+    /// Fraction of the keys requested through this client which were found, between 0 and 1.
+    ///
+    /// This is a basic signal of cache effectiveness, derived from [`Client::get_hits`] and
+    /// [`Client::get_misses`].  If no keys have been requested, 0 is returned.
+    pub fn hit_ratio(&self) -> f64 {
+        let total = self.get_hits + self.get_misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.get_hits as f64 / total as f64
+        }
+    }
+
+    /// Counts the keys found and not found by a retrieval command.
+    fn record_lookups(&mut self, requested: usize, found: usize) {
+        self.get_hits += found as u64;
+        self.get_misses += requested.saturating_sub(found) as u64;
     }
 
+    /// Number of bytes currently reserved in the read buffer before each read from the connection.
+    ///
+    /// This starts at 1KB, or at [`ClientOptions::read_buffer_capacity`] if it is set, and adapts
+    /// to the average size of the responses read so far, up to 1MB, so that workloads with
+    /// consistently large values need fewer reads per response.
+    pub fn read_chunk_size(&self) -> usize {
+        self.read_chunk_size
+    }
+
+    /// Smallest number of bytes reserved in the read buffer before each read from the connection.
+    fn min_read_chunk_size(&self) -> usize {
+        self.options
+            .read_buffer_capacity
+            .unwrap_or(MIN_READ_CHUNK_SIZE)
+            .max(MIN_READ_CHUNK_SIZE)
+    }
+
+    /// Updates the running average response size, and the read chunk size derived from it.
+    fn record_response_size(&mut self, n: usize) {
+        // An exponential moving average, so the chunk size follows changes in the workload
+        // without being swayed too far by a single outlier.
+        self.avg_response_size = if self.avg_response_size == 0 {
+            n
+        } else {
+            (self.avg_response_size * 7 + n) / 8
+        };
+        let min = self.min_read_chunk_size();
+        self.read_chunk_size = self
+            .avg_response_size
+            .next_power_of_two()
+            .clamp(min, MAX_READ_CHUNK_SIZE.max(min));
+
+        if let Some(max) = self.options.read_buffer_high_water {
+            self.read_chunk_size = self.read_chunk_size.min(max.max(MIN_READ_CHUNK_SIZE));
+            self.shrink_buf |= n > max;
+        }
+    }
+
+    /// Replaces the read buffer with a smaller one, keeping any bytes not yet consumed, if a
+    /// response larger than [`ClientOptions::read_buffer_high_water`] has been read.
+    fn maybe_shrink_buf(&mut self) {
+        if !std::mem::take(&mut self.shrink_buf) {
+            return;
+        }
+
+        let mut buf = BytesMut::with_capacity(self.read_chunk_size.max(self.buf.len()));
+        buf.extend_from_slice(&self.buf);
+        self.buf = buf;
+    }
+
+    /// Reads and parses a single response with `op`.
+    ///
+    /// This is not cancellation-safe on its own, so the client is marked as poisoned until a
+    /// response has been fully read.  If the future is dropped before then, or reading fails part
+    /// way through a response, the client stays poisoned and all subsequent reads fail rather
+    /// than returning another command's response.  Only a response which could not be parsed
+    /// leaves the client usable.
     pub(crate) async fn drive_receive<R, F>(&mut self, op: F) -> Result<R, Error>
+    where
+        F: Fn(&[u8]) -> Result<Option<(usize, R)>, ErrorKind>,
+    {
+        self.check_poisoned()?;
+
+        self.poisoned = true;
+        let result = self.read_response(op).await;
+        self.poisoned = match &result {
+            Ok(_) => {
+                self.last_response = Instant::now();
+                false
+            }
+            // The rest of a line which was too long is still unread, and would be read as the next
+            // response.
+            Err(e) if matches!(e, Error::Protocol(_) | Error::Command(_)) => is_line_too_long(e),
+            // The connection failed or timed out, so the rest of the response may still arrive.
+            Err(_) => true,
+        };
+        result
+    }
+
+    /// Returns an error if this client is poisoned, so must not be used for further commands.
+    fn check_poisoned(&self) -> Result<(), Error> {
+        if self.poisoned {
+            return Err(Error::Transport(std::io::Error::other(
+                "client was poisoned by a cancelled or desynchronized command",
+            )));
+        }
+        Ok(())
+    }
+
+    async fn read_response<R, F>(&mut self, op: F) -> Result<R, Error>
     where
         F: Fn(&[u8]) -> Result<Option<(usize, R)>, ErrorKind>,
     {
         // If we serviced a previous request, advance our buffer forward.
-        if let Some(n) = self.last_read_n {
+        if let Some(n) = self.last_read_n.take() {
             let _ = self.buf.split_to(n);
         }
+        self.maybe_shrink_buf();
 
         let mut needs_more_data = false;
         loop {
             if self.buf.is_empty() || needs_more_data {
-                match self.conn {
-                    Connection::Tcp(ref mut s) => {
-                        self.buf.reserve(1024);
-                        let n = s.read_buf(&mut self.buf).await?;
-                        if n == 0 {
-                            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
-                        }
-                    }
-                    Connection::Unix(ref mut s) => {
-                        self.buf.reserve(1024);
-                        let n = s.read_buf(&mut self.buf).await?;
-                        if n == 0 {
-                            return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
-                        }
-                    }
-                }
+                self.buf.reserve(self.read_chunk_size);
+                self.fill_buf().await?;
             }
 
             // Try and parse out a response.
@@ -81,10 +547,20 @@ impl Client {
                 // We got a response.
                 Ok(Some((n, response))) => {
                     self.last_read_n = Some(n);
+                    self.record_response_size(n);
                     return Ok(response);
                 }
-                // We didn't have enough data, so loop around and try again.
+                // We didn't have enough data, so loop around and try again, unless the line being
+                // parsed is already too long.
                 Ok(None) => {
+                    if let Some(max) = self.options.max_line_bytes {
+                        if self.buf.len() > max && !self.buf[..max].contains(&b'\n') {
+                            return Err(Status::Error(ErrorKind::Protocol(Some(
+                                LINE_TOO_LONG.into(),
+                            )))
+                            .into());
+                        }
+                    }
                     needs_more_data = true;
                     continue;
                 }
@@ -94,13 +570,54 @@ impl Client {
         }
     }
 
+    /// Reads more data from the connection into the read buffer, within the operation timeout.
+    ///
+    /// Returns [`Error::Timeout`] for the `read` operation if the timeout elapses, and an
+    /// unexpected EOF error if the connection was closed.
+    async fn fill_buf(&mut self) -> Result<usize, Error> {
+        let read = self.conn.read_buf(&mut self.buf);
+        let n = match self.options.operation_timeout {
+            Some(after) => match tokio::time::timeout(after, read).await {
+                Ok(n) => n?,
+                Err(_) => return Err(self.timeout_error("read", after)),
+            },
+            None => read.await?,
+        };
+        if n == 0 {
+            return Err(Error::Transport(std::io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(n)
+    }
+
     pub(crate) async fn get_read_write_response(&mut self) -> Result<Response, Error> {
-        self.drive_receive(parse_ascii_response).await
+        match self.drive_receive(parse_ascii_response).await? {
+            Response::Status(Status::Error(ErrorKind::Client(msg))) if is_desync_error(&msg) => {
+                Err(self.desync(msg))
+            }
+            Response::Status(Status::Error(ErrorKind::BadCommandFormat)) => {
+                Err(self.bad_command_format())
+            }
+            r => Ok(r),
+        }
+    }
+
+    /// Poisons the client, as the server could not parse the framing of a command, so may read
+    /// what followed it as further commands.
+    fn bad_command_format(&mut self) -> Error {
+        self.poisoned = true;
+        Status::Error(ErrorKind::BadCommandFormat).into()
+    }
+
+    /// Poisons the client, as the connection is no longer aligned with the protocol.
+    fn desync(&mut self, msg: String) -> Error {
+        self.poisoned = true;
+        Error::ProtocolDesync(msg)
     }
 
     pub(crate) async fn map_set_multi_responses<'a, K, V>(
         &mut self,
         kv: &'a [(K, V)],
+        command: &str,
     ) -> Result<FxHashMap<&'a K, Result<(), Error>>, Error>
     where
         K: AsRef<[u8]> + Eq + std::hash::Hash,
@@ -109,10 +626,10 @@ impl Client {
         let mut results = FxHashMap::with_capacity_and_hasher(kv.len(), Default::default());
 
         for (key, _) in kv {
-            let result = match self.drive_receive(parse_ascii_response).await {
+            let result = match self.get_read_write_response().await {
                 Ok(Response::Status(Status::Stored)) => Ok(()),
                 Ok(Response::Status(s)) => Err(s.into()),
-                Ok(_) => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+                Ok(r) => Err(Status::Error(r.unexpected(command)).into()),
                 Err(e) => return Err(e),
             };
 
@@ -122,6 +639,211 @@ impl Client {
         Ok(results)
     }
 
+    /// Writes a storage command for the given key and value.
+    ///
+    /// The command line is formatted into a small buffer, and written along with the value as
+    /// separate slices, so the value is never copied into a staging buffer.
+    pub(crate) async fn write_storage_command(
+        &mut self,
+        command: &[u8],
+        key: &[u8],
+        value: &[u8],
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error> {
+        self.write_prefixed_storage_command(command, b"", key, value, ttl, flags)
+            .await
+    }
+
+    /// Writes a storage command for the key made up of `prefix` followed by `key`, and the value.
+    ///
+    /// The prefix and key are written as separate slices, along with the rest of the command line
+    /// and the value, so they are never concatenated into a new key.
+    async fn write_prefixed_storage_command(
+        &mut self,
+        command: &[u8],
+        prefix: &[u8],
+        key: &[u8],
+        value: &[u8],
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error> {
+        let mut fields = Vec::with_capacity(48);
+        self.write_storage_fields(&mut fields, value.len() as u64, ttl, flags);
+
+        let line = [command, b" ", prefix, key, &fields];
+        debug_assert!(
+            is_storage_command_line(&line.concat()),
+            "malformed storage command line: {:?}",
+            String::from_utf8_lossy(&line.concat())
+        );
+        self.write_all_vectored(&[command, b" ", prefix, key, &fields, value, b"\r\n"])
+            .await
+    }
+
+    /// Writes and flushes a storage command with `noreply`, so no response is read for it.
+    async fn write_storage_command_no_reply(
+        &mut self,
+        command: &[u8],
+        key: &[u8],
+        value: &[u8],
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error> {
+        let mut header = self.storage_header(command, key, value.len() as u64, ttl, flags);
+        header.truncate(header.len() - 2);
+        header.extend(b" noreply\r\n");
+
+        self.write_all_vectored(&[&header, value, b"\r\n"]).await?;
+        self.conn.flush().await?;
+        Ok(())
+    }
+
+    /// Formats the command line of a storage command, for a value of `len` bytes.
+    fn storage_header(
+        &self,
+        command: &[u8],
+        key: &[u8],
+        len: u64,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Vec<u8> {
+        let mut header = Vec::with_capacity(command.len() + key.len() + 48);
+        header.extend(command);
+        header.extend(b" ");
+        header.extend(key);
+        self.write_storage_fields(&mut header, len, ttl, flags);
+
+        debug_assert!(
+            is_storage_command_line(&header),
+            "malformed storage command line: {:?}",
+            String::from_utf8_lossy(&header)
+        );
+        header
+    }
+
+    /// Formats the fields which follow the key in the command line of a storage command, for a
+    /// value of `len` bytes, up to and including the CRLF.
+    fn write_storage_fields(
+        &self,
+        out: &mut Vec<u8>,
+        len: u64,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) {
+        out.extend(b" ");
+        out.extend(self.storage_flags(flags).to_string().as_bytes());
+        out.extend(b" ");
+        out.extend(self.storage_ttl(ttl).to_string().as_bytes());
+        out.extend(b" ");
+        out.extend(len.to_string().as_bytes());
+        out.extend(b"\r\n");
+    }
+
+    /// Writes all of the given parts, with vectored writes if the connection supports them.
+    ///
+    /// Large parts, such as values, bypass the write buffer entirely, so writing them as separate
+    /// slices avoids both copying them and a separate write for each part.  Otherwise, the parts
+    /// are written one at a time.
+    pub(crate) async fn write_all_vectored(&mut self, parts: &[&[u8]]) -> Result<(), Error> {
+        if !self.conn.is_write_vectored() {
+            for part in parts {
+                self.conn.write_all(part).await?;
+            }
+            return Ok(());
+        }
+
+        let total: usize = parts.iter().map(|p| p.len()).sum();
+        let mut written = 0;
+        while written < total {
+            let mut skip = written;
+            let slices: Vec<IoSlice<'_>> = parts
+                .iter()
+                .filter_map(|part| {
+                    if skip >= part.len() {
+                        skip -= part.len();
+                        None
+                    } else {
+                        let slice = IoSlice::new(&part[skip..]);
+                        skip = 0;
+                        Some(slice)
+                    }
+                })
+                .collect();
+
+            let n = self.conn.write_vectored(&slices).await?;
+            if n == 0 {
+                return Err(Error::Transport(std::io::ErrorKind::WriteZero.into()));
+            }
+            written += n;
+        }
+
+        Ok(())
+    }
+
+    /// Checks whether the server supports meta commands, by sending a meta no-op the first time.
+    pub(crate) async fn supports_meta(&mut self) -> Result<bool, Error> {
+        if let Some(supported) = self.meta_supported {
+            return Ok(supported);
+        }
+
+        self.conn.write_all(b"mn\r\n").await?;
+        self.conn.flush().await?;
+
+        let supported = match self.drive_receive(parse_ascii_line).await?.as_slice() {
+            b"MN" => true,
+            b"ERROR" => false,
+            line => {
+                return Err(Status::Error(ErrorKind::Protocol(Some(format!(
+                    "Invalid response for `mn` command: `{}`",
+                    String::from_utf8_lossy(line)
+                ))))
+                .into())
+            }
+        };
+        self.meta_supported = Some(supported);
+        Ok(supported)
+    }
+
+    pub(crate) async fn get_value_response(&mut self) -> Result<ValueResponse, Error> {
+        match self.drive_receive(parse_ascii_value_response).await? {
+            ValueResponse::Status(Status::Error(ErrorKind::BadCommandFormat)) => {
+                Err(self.bad_command_format())
+            }
+            r => Ok(r),
+        }
+    }
+
+    /// Reads the header of the response to a `get` for a single key, whose data block is then read
+    /// by the caller.
+    async fn get_value_header_response(&mut self) -> Result<ValueHeaderResponse, Error> {
+        match self.drive_receive(parse_ascii_value_header).await? {
+            ValueHeaderResponse::Status(Status::Error(ErrorKind::Client(msg)))
+                if is_desync_error(&msg) =>
+            {
+                Err(self.desync(msg))
+            }
+            ValueHeaderResponse::Status(Status::Error(ErrorKind::BadCommandFormat)) => {
+                Err(self.bad_command_format())
+            }
+            r => Ok(r),
+        }
+    }
+
+    pub(crate) async fn get_meta_response(&mut self) -> Result<MetaResponse, Error> {
+        match self.drive_receive(parse_ascii_meta_response).await? {
+            MetaResponse::Status(Status::Error(ErrorKind::Client(msg)))
+                if is_desync_error(&msg) =>
+            {
+                Err(self.desync(msg))
+            }
+            MetaResponse::Status(Status::Error(ErrorKind::BadCommandFormat)) => {
+                Err(self.bad_command_format())
+            }
+            r => Ok(r),
+        }
+    }
+
     pub(crate) async fn get_metadump_response(&mut self) -> Result<MetadumpResponse, Error> {
         self.drive_receive(parse_ascii_metadump_response).await
     }
@@ -136,52 +858,477 @@ impl Client {
     ///
     /// Otherwise, [`Error`] is returned.
     pub async fn get<K: AsRef<[u8]>>(&mut self, key: K) -> Result<Option<Value>, Error> {
-        self.conn
-            .write_all(&[b"get ", key.as_ref(), b"\r\n"].concat())
+        self.get_prefixed_key(b"", key.as_ref()).await
+    }
+
+    /// Gets the key made up of `prefix` followed by `key`.
+    ///
+    /// The prefix and key are written to the connection as separate slices, so namespaced keys
+    /// can be used without concatenating them into a new key for every command.  Otherwise, this
+    /// behaves exactly like [`Client::get`], and the returned [`Value::key`] is the full key.
+    ///
+    /// ```no_run
+    /// # use async_memcached::{Client, Error};
+    /// # async fn example(client: &mut Client) -> Result<(), Error> {
+    /// client.set_with_prefix("users:", "42", "alice", None, None).await?;
+    /// let value = client.get_with_prefix("users:", "42").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn get_with_prefix<P, K>(&mut self, prefix: P, key: K) -> Result<Option<Value>, Error>
+    where
+        P: AsRef<[u8]>,
+        K: AsRef<[u8]>,
+    {
+        self.get_prefixed_key(prefix.as_ref(), key.as_ref()).await
+    }
+
+    async fn get_prefixed_key(
+        &mut self,
+        prefix: &[u8],
+        key: &[u8],
+    ) -> Result<Option<Value>, Error> {
+        self.write_all_vectored(&[b"get ", prefix, key, b"\r\n"])
+            .await?;
+        self.conn.flush().await?;
+
+        let result = self.get_response().await;
+        if let Ok(value) = &result {
+            self.record_lookups(1, value.is_some() as usize);
+        }
+        result
+    }
+
+    /// Gets the given key, along with its CAS identifier.
+    ///
+    /// This behaves exactly like [`Client::get`], except that [`Value::cas`] is populated, so the
+    /// value can then be updated with a compare-and-swap, which only succeeds if the key has not
+    /// been changed since.  [`Client::get`] always returns a `cas` of `None`.
+    pub async fn gets<K: AsRef<[u8]>>(&mut self, key: K) -> Result<Option<Value>, Error> {
+        self.write_all_vectored(&[b"gets ", key.as_ref(), b"\r\n"])
+            .await?;
+        self.conn.flush().await?;
+
+        let result = self.get_response().await;
+        if let Ok(value) = &result {
+            self.record_lookups(1, value.is_some() as usize);
+        }
+        result
+    }
+
+    /// Gets the given key and updates its TTL, in a single round trip.
+    ///
+    /// If the key is found, `Some(Value)` is returned, and its TTL is set to `ttl` seconds, while
+    /// `None` is returned if it was not found.  Otherwise, [`Error`] is returned.
+    pub async fn gat<K: AsRef<[u8]>>(&mut self, ttl: i64, key: K) -> Result<Option<Value>, Error> {
+        self.get_and_touch(b"gat ", ttl, key.as_ref()).await
+    }
+
+    /// Gets the given key along with its CAS identifier, and updates its TTL, in a single round
+    /// trip.
+    ///
+    /// This behaves exactly like [`Client::gat`], except that [`Value::cas`] is populated, as for
+    /// [`Client::gets`].
+    pub async fn gats<K: AsRef<[u8]>>(&mut self, ttl: i64, key: K) -> Result<Option<Value>, Error> {
+        self.get_and_touch(b"gats ", ttl, key.as_ref()).await
+    }
+
+    async fn get_and_touch(
+        &mut self,
+        command: &[u8],
+        ttl: i64,
+        key: &[u8],
+    ) -> Result<Option<Value>, Error> {
+        // Unlike the other retrieval commands, the TTL comes before the key.
+        self.write_all_vectored(&[command, ttl.to_string().as_bytes(), b" ", key, b"\r\n"])
             .await?;
         self.conn.flush().await?;
 
+        let result = self.get_response().await;
+        if let Ok(value) = &result {
+            self.record_lookups(1, value.is_some() as usize);
+        }
+        result
+    }
+
+    /// Reads the response to a `get` or `gets` for a single key.
+    async fn get_response(&mut self) -> Result<Option<Value>, Error> {
         match self.get_read_write_response().await? {
             Response::Status(Status::NotFound) => Ok(None),
             Response::Status(s) => Err(s.into()),
             Response::Data(d) => d
                 .map(|mut items| {
                     if items.len() != 1 {
-                        Err(Status::Error(ErrorKind::Protocol(None)).into())
+                        Err(Status::Error(ErrorKind::Protocol(Some(format!(
+                            "expected 1 value in response to get, got {}",
+                            items.len()
+                        ))))
+                        .into())
                     } else {
                         Ok(items.remove(0))
                     }
                 })
                 .transpose(),
-            _ => Err(Error::Protocol(Status::Error(ErrorKind::Protocol(None)))),
+            r => Err(Error::Protocol(r.unexpected("get"))),
         }
     }
 
-    /// Gets the given keys.
+    /// Gets the given key, streaming its data into `writer`.
     ///
-    /// If any of the keys are found, a vector of [`Value`] will be returned, where [`Value`]
-    /// describes the metadata and data of the key.
+    /// The data is copied from the connection to `writer` one read at a time, so large values
+    /// never need to be held in memory in their entirety.  If the key is found, `Some(usize)` is
+    /// returned with the number of bytes written, and `None` is returned if it was not found.
     ///
-    /// Otherwise, [`Error`] is returned.
+    /// Otherwise, [`Error`] is returned.  Each read of the data is limited by the
+    /// [operation timeout](ClientOptions::operation_timeout), like any other response.  If reading
+    /// the data or writing it to `writer` fails part way through, the rest of the response is left
+    /// unread and the client is poisoned.
+    pub async fn get_to_writer<K, W>(
+        &mut self,
+        key: K,
+        mut writer: W,
+    ) -> Result<Option<usize>, Error>
+    where
+        K: AsRef<[u8]>,
+        W: AsyncWrite + Unpin,
+    {
+        self.write_all_vectored(&[b"get ", key.as_ref(), b"\r\n"])
+            .await?;
+        self.conn.flush().await?;
+
+        let len = match self.get_value_header_response().await? {
+            ValueHeaderResponse::Header { len, .. } => len,
+            ValueHeaderResponse::End => {
+                self.record_lookups(1, 0);
+                return Ok(None);
+            }
+            ValueHeaderResponse::Status(s) => return Err(s.into()),
+        };
+        self.record_lookups(1, 1);
+
+        // The data block is copied around `drive_receive`, so consume the header now rather than
+        // on the next read, and then drain whatever part of the data was already buffered.
+        if let Some(n) = self.last_read_n.take() {
+            let _ = self.buf.split_to(n);
+        }
+        // Copying the data is not cancellation-safe either, so stay poisoned until it's done.
+        self.poisoned = true;
+        let mut remaining = len;
+        loop {
+            let n = remaining.min(self.buf.len() as u64) as usize;
+            writer.write_all(&self.buf.split_to(n)).await?;
+            remaining -= n as u64;
+            if remaining == 0 {
+                break;
+            }
+            self.buf.reserve(self.read_chunk_size);
+            self.fill_buf().await?;
+        }
+        writer.flush().await?;
+        self.poisoned = false;
+
+        self.drive_receive(parse_ascii_value_trailer).await?;
+
+        Ok(Some(len as usize))
+    }
+
+    /// Gets the given key, returning its data without copying it out of the read buffer.
+    ///
+    /// The data is read straight into the client's read buffer and split off from it as a
+    /// reference-counted [`Bytes`](bytes::Bytes), which remains valid however the buffer is reused
+    /// by later commands.  This avoids copying large values.  If the key is found, [`BytesValue`]
+    /// is returned with the same key, flags and CAS as [`Client::get`] would return, and `None` is
+    /// returned if it was not found.
+    ///
+    /// Otherwise, [`Error`] is returned.  Each read of the data is limited by the
+    /// [operation timeout](ClientOptions::operation_timeout), like any other response.  If reading
+    /// the data fails part way through, the rest of the response is left unread and the client is
+    /// poisoned.
+    pub async fn get_bytes<K: AsRef<[u8]>>(&mut self, key: K) -> Result<Option<BytesValue>, Error> {
+        self.write_all_vectored(&[b"get ", key.as_ref(), b"\r\n"])
+            .await?;
+        self.conn.flush().await?;
+
+        let (key, flags, len, cas) = match self.get_value_header_response().await? {
+            ValueHeaderResponse::Header {
+                key,
+                flags,
+                len,
+                cas,
+            } => (key, flags, len as usize, cas),
+            ValueHeaderResponse::End => {
+                self.record_lookups(1, 0);
+                return Ok(None);
+            }
+            ValueHeaderResponse::Status(s) => return Err(s.into()),
+        };
+        self.record_lookups(1, 1);
+
+        // Consume the header now, so the data starts at the front of the buffer, and read the
+        // rest of the data into the buffer in as few reads as possible.
+        if let Some(n) = self.last_read_n.take() {
+            let _ = self.buf.split_to(n);
+        }
+        self.poisoned = true;
+        while self.buf.len() < len {
+            self.buf.reserve(len - self.buf.len());
+            self.fill_buf().await?;
+        }
+        self.poisoned = false;
+        let data = self.buf.split_to(len).freeze();
+        self.record_response_size(len);
+
+        self.drive_receive(parse_ascii_value_trailer).await?;
+
+        Ok(Some(BytesValue {
+            key,
+            cas,
+            flags,
+            data,
+        }))
+    }
+
+    /// Gets the key written by `write_key`.
+    ///
+    /// `write_key` is given a buffer owned by this client, which is cleared and reused across
+    /// calls, so keys can be formatted without allocating.  Otherwise, this behaves exactly like
+    /// [`Client::get`].  To prepend a namespace to a key, [`Client::get_with_prefix`] avoids
+    /// copying either of them.
+    pub async fn get_with_key_writer<F>(&mut self, write_key: F) -> Result<Option<Value>, Error>
+    where
+        F: FnOnce(&mut Vec<u8>),
+    {
+        let mut key = std::mem::take(&mut self.key_buf);
+        key.clear();
+        write_key(&mut key);
+
+        let result = self.get(&key).await;
+        self.key_buf = key;
+        result
+    }
+
+    /// Gets the given keys.
+    ///
+    /// A vector of [`Value`] is returned for the keys which were found, where [`Value`] describes
+    /// the metadata and data of the key.  If none of the keys are found, as the server then
+    /// responds with a bare end marker, an empty vector is returned.  If no keys are given, an
+    /// empty vector is returned without sending a command.
+    ///
+    /// The keys are iterated over once, so a slice such as `&keys` or `keys.as_slice()` can be
+    /// passed to borrow them, rather than cloning a collection of owned keys.
+    ///
+    /// All of the keys are requested with a single `get` command, to which the server responds
+    /// with only the values it found, followed by one end marker.  Keys which were not found are
+    /// simply absent from the result, so misses cost nothing beyond their bytes in the command;
+    /// [`Client::get_multi_with_misses`] returns them explicitly.  This is as close to quiet gets
+    /// as the classic ASCII protocol allows, as a `get` for a single key always gets a response.
+    /// Truly quiet gets, where each key is its own command and misses produce no response, are
+    /// only available through the `q` flag of meta commands.
+    ///
+    /// Otherwise, [`Error`] is returned.
     pub async fn get_multi<I, K>(&mut self, keys: I) -> Result<Vec<Value>, Error>
     where
         I: IntoIterator<Item = K>,
         K: AsRef<[u8]>,
     {
+        let mut keys = keys.into_iter().peekable();
+        if keys.peek().is_none() {
+            return Ok(Vec::new());
+        }
+
+        // Keys are split across several commands if a single one would exceed the maximum length
+        // of a command line, counting its trailing `\r\n`.
+        let max_command_bytes = self.options.max_command_bytes.unwrap_or(usize::MAX);
         let mut bf = Vec::new();
+        let mut requested = 0;
+        let mut commands = 1;
+        let mut line_len = 3;
         bf.extend(b"get");
         for key in keys {
+            let key = key.as_ref();
+            if line_len > 3 && line_len + 1 + key.len() + 2 > max_command_bytes {
+                bf.extend(b"\r\nget");
+                line_len = 3;
+                commands += 1;
+            }
             bf.extend(b" ");
-            bf.extend(key.as_ref());
+            bf.extend(key);
+            line_len += 1 + key.len();
+            requested += 1;
         }
         bf.extend(b"\r\n");
         self.conn.write_all(&bf).await?;
         self.conn.flush().await?;
 
-        match self.get_read_write_response().await? {
-            Response::Status(s) => Err(s.into()),
-            Response::Data(d) => d.ok_or(Status::NotFound.into()),
-            _ => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+        // Values are consumed one at a time, so large responses never need to be buffered whole.
+        // Every command's response is read even if one fails, so none are left on the connection.
+        let mut values = Vec::new();
+        let mut first_err = None;
+        for _ in 0..commands {
+            loop {
+                match self.get_value_response().await? {
+                    ValueResponse::Value(v) => values.push(v),
+                    ValueResponse::End => break,
+                    ValueResponse::Status(s) => {
+                        first_err.get_or_insert(s);
+                        break;
+                    }
+                }
+            }
+        }
+        if let Some(s) = first_err {
+            return Err(s.into());
+        }
+        self.record_lookups(requested, values.len());
+
+        Ok(values)
+    }
+
+    /// Gets the given keys, returning the values which were found in a map keyed by their key.
+    ///
+    /// This issues a single multi-key `get`, like [`Client::get_multi`], and keys each value by the
+    /// key in its `VALUE` line, so callers don't have to re-associate values with the keys they
+    /// requested.  Keys which were not found are simply absent from the map.
+    pub async fn get_multi_map<I, K>(&mut self, keys: I) -> Result<FxHashMap<Vec<u8>, Value>, Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let values = self.get_multi(keys).await?;
+
+        let mut hits: FxHashMap<Vec<u8>, Value> =
+            FxHashMap::with_capacity_and_hasher(values.len(), Default::default());
+        for value in values {
+            hits.insert(value.key.clone(), value);
+        }
+
+        Ok(hits)
+    }
+
+    /// Gets the given keys, returning both the values which were found and the keys which were not.
+    ///
+    /// Values are returned in a map keyed by their key, and the missing keys are returned in the
+    /// order they were requested, without duplicates.  Only the keys themselves are held on to, so
+    /// passing a slice of keys borrows them rather than cloning them.
+    pub async fn get_multi_with_misses<I, K>(
+        &mut self,
+        keys: I,
+    ) -> Result<(FxHashMap<Vec<u8>, Value>, Vec<Vec<u8>>), Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let keys: Vec<K> = keys.into_iter().collect();
+
+        let hits = self.get_multi_map(&keys).await?;
+
+        let mut missed = FxHashSet::default();
+        let mut misses = Vec::new();
+        for key in &keys {
+            let key = key.as_ref();
+            if !hits.contains_key(key) && missed.insert(key) {
+                misses.push(key.to_vec());
+            }
+        }
+
+        Ok((hits, misses))
+    }
+
+    /// Gets the given keys, splitting them across pipelined commands.
+    ///
+    /// Keys are requested in chunks of 100, with up to `window` chunks in flight at once: the next
+    /// chunk is written before the responses for earlier chunks have been read, which overlaps
+    /// network latency without building a single giant command.  A `window` of 0 is treated as 1.
+    ///
+    /// A vector of [`Value`] is returned for the keys which were found, in the same order as
+    /// [`Client::get_multi`] would return them, and so is empty if none of the keys are found or
+    /// no keys are given.  Otherwise, [`Error`] is returned.  If the server returns an error for a
+    /// chunk, no further chunks are written, but the responses for chunks already in flight are
+    /// still read before the error is returned.  If writing a chunk or reading a response fails,
+    /// the responses still in flight can no longer be read, so the client is poisoned.
+    pub async fn get_multi_pipelined<I, K>(
+        &mut self,
+        keys: I,
+        window: usize,
+    ) -> Result<Vec<Value>, Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let keys: Vec<K> = keys.into_iter().collect();
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let chunks: Vec<&[K]> = keys.chunks(PIPELINED_GET_CHUNK_SIZE).collect();
+        let window = window.max(1);
+
+        let mut values = Vec::new();
+        let mut first_err = None;
+        if let Err(e) = self
+            .pipeline_get_chunks(&chunks, window, &mut values, &mut first_err)
+            .await
+        {
+            // Responses to chunks which were written but not read are still on their way, as is
+            // the rest of any response which failed part way through.
+            self.poisoned = true;
+            return Err(e);
+        }
+
+        match first_err {
+            Some(s) => Err(s.into()),
+            None => Ok(values),
+        }
+    }
+
+    /// Writes a `get` for each chunk of keys, keeping up to `window` in flight, and reads their
+    /// values into `values`.
+    ///
+    /// Once the server returns an error for a chunk, it is kept in `first_err`, and no further
+    /// chunks are written, but the responses for those already in flight are still read.
+    async fn pipeline_get_chunks<K: AsRef<[u8]>>(
+        &mut self,
+        chunks: &[&[K]],
+        window: usize,
+        values: &mut Vec<Value>,
+        first_err: &mut Option<Status>,
+    ) -> Result<(), Error> {
+        let mut written = 0;
+        let mut read = 0;
+        loop {
+            if first_err.is_none() && written < chunks.len() && written - read < window {
+                while written < chunks.len() && written - read < window {
+                    let mut bf = Vec::new();
+                    bf.extend(b"get");
+                    for key in chunks[written] {
+                        bf.extend(b" ");
+                        bf.extend(key.as_ref());
+                    }
+                    bf.extend(b"\r\n");
+                    self.conn.write_all(&bf).await?;
+                    written += 1;
+                }
+                self.conn.flush().await?;
+            }
+
+            if read == written {
+                return Ok(());
+            }
+
+            let found = values.len();
+            loop {
+                match self.get_value_response().await? {
+                    ValueResponse::Value(v) => values.push(v),
+                    ValueResponse::End => {
+                        self.record_lookups(chunks[read].len(), values.len() - found);
+                        break;
+                    }
+                    ValueResponse::Status(s) => {
+                        first_err.get_or_insert(s);
+                        break;
+                    }
+                }
+            }
+            read += 1;
         }
     }
 
@@ -202,8 +1349,9 @@ impl Client {
 
     /// Sets the given key.
     ///
-    /// If `ttl` or `flags` are not specified, they will default to 0.  If the value is set
-    /// successfully, `()` is returned, otherwise [`Error`] is returned.
+    /// If `ttl` or `flags` are not specified, they will default to those set with
+    /// [`Client::with_default_ttl`] and [`Client::with_default_flags`], or to 0 otherwise.  If the
+    /// value is set successfully, `()` is returned, otherwise [`Error`] is returned.
     pub async fn set<K, V>(
         &mut self,
         key: K,
@@ -218,80 +1366,103 @@ impl Client {
         let kr = key.as_ref();
         let vr = value.as_bytes();
 
-        self.conn.write_all(b"set ").await?;
-        self.conn.write_all(kr).await?;
-
-        let flags = flags.unwrap_or(0).to_string();
-        self.conn.write_all(b" ").await?;
-        self.conn.write_all(flags.as_ref()).await?;
-
-        let ttl = ttl.unwrap_or(0).to_string();
-        self.conn.write_all(b" ").await?;
-        self.conn.write_all(ttl.as_ref()).await?;
+        self.write_storage_command(b"set", kr, vr.as_ref(), ttl, flags)
+            .await?;
+        self.conn.flush().await?;
 
-        let vlen = vr.len().to_string();
-        self.conn.write_all(b" ").await?;
-        self.conn.write_all(vlen.as_ref()).await?;
-        self.conn.write_all(b"\r\n").await?;
+        self.set_response().await
+    }
 
-        self.conn.write_all(vr.as_ref()).await?;
-        self.conn.write_all(b"\r\n").await?;
+    /// Sets the key made up of `prefix` followed by `key`.
+    ///
+    /// The prefix and key are written to the connection as separate slices, so namespaced keys
+    /// can be used without concatenating them into a new key for every command.  Otherwise, this
+    /// behaves exactly like [`Client::set`].
+    pub async fn set_with_prefix<P, K, V>(
+        &mut self,
+        prefix: P,
+        key: K,
+        value: V,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<[u8]>,
+        K: AsRef<[u8]>,
+        V: AsMemcachedValue,
+    {
+        let vr = value.as_bytes();
 
+        self.write_prefixed_storage_command(
+            b"set",
+            prefix.as_ref(),
+            key.as_ref(),
+            vr.as_ref(),
+            ttl,
+            flags,
+        )
+        .await?;
         self.conn.flush().await?;
 
+        self.set_response().await
+    }
+
+    async fn set_response(&mut self) -> Result<(), Error> {
         match self.get_read_write_response().await? {
             Response::Status(Status::Stored) => Ok(()),
             Response::Status(s) => Err(s.into()),
-            _ => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+            r => Err(Status::Error(r.unexpected("set")).into()),
         }
     }
 
-    /// Sets multiple keys and values through pipelined commands.
+    /// Sets the given key only if it has not been changed since its CAS identifier `cas` was
+    /// read, e.g. through [`Client::gets`].
     ///
-    /// If `ttl` or `flags` are not specified, they will default to 0. The same values for `ttl` and `flags` will be applied to each key.
-    /// Returns a result with a HashMap of keys mapped to the result of the set operation, or an error.
-    pub async fn set_multi<'a, K, V>(
+    /// If `ttl` or `flags` are not specified, they default as for [`Client::set`].  If the value
+    /// is stored, `()` is returned.  If the key has been changed since, `Err(Command(Exists))`
+    /// is returned, in which case the key can be read again and the update retried, and if the
+    /// key no longer exists, `Err(Command(NotFound))` is returned.  Otherwise, [`Error`] is
+    /// returned.
+    pub async fn cas<K, V>(
         &mut self,
-        kv: &'a [(K, V)],
+        key: K,
+        value: V,
         ttl: Option<i64>,
         flags: Option<u32>,
-    ) -> Result<FxHashMap<&'a K, Result<(), Error>>, Error>
+        cas: u64,
+    ) -> Result<(), Error>
     where
-        K: AsRef<[u8]> + Eq + std::hash::Hash + std::fmt::Debug,
+        K: AsRef<[u8]>,
         V: AsMemcachedValue,
     {
-        for (key, value) in kv {
-            let kr = key.as_ref();
-            let vr = value.as_bytes();
-
-            self.conn.write_all(b"set ").await?;
-            self.conn.write_all(kr).await?;
-
-            let flags = flags.unwrap_or(0).to_string();
-            self.conn.write_all(b" ").await?;
-            self.conn.write_all(flags.as_ref()).await?;
-
-            let ttl = ttl.unwrap_or(0).to_string();
-            self.conn.write_all(b" ").await?;
-            self.conn.write_all(ttl.as_ref()).await?;
-
-            let vlen = vr.len().to_string();
-            self.conn.write_all(b" ").await?;
-            self.conn.write_all(vlen.as_ref()).await?;
-            self.conn.write_all(b"\r\n").await?;
+        let vr = value.as_bytes();
+        let mut header = self.storage_header(b"cas", key.as_ref(), vr.len() as u64, ttl, flags);
+        header.truncate(header.len() - 2);
+        header.extend(b" ");
+        header.extend(cas.to_string().as_bytes());
+        header.extend(b"\r\n");
 
-            self.conn.write_all(vr.as_ref()).await?;
-            self.conn.write_all(b"\r\n").await?;
-        }
+        self.write_all_vectored(&[&header, vr.as_ref(), b"\r\n"])
+            .await?;
         self.conn.flush().await?;
 
-        let results = self.map_set_multi_responses(kv).await?;
-
-        Ok(results)
+        match self.get_read_write_response().await? {
+            Response::Status(Status::Stored) => Ok(()),
+            Response::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("cas")).into()),
+        }
     }
 
-    /// Add a key. If the value exists, Err(Protocol(NotStored)) is returned.
-    pub async fn add<K, V>(
+    /// Sets the given key but doesn't wait for a reply.
+    ///
+    /// `ttl` and `flags` default as for [`Client::set`].  Commands on a connection are processed
+    /// by the server in the order they are sent, so any later command on this client, such as
+    /// [`Client::get`], sees the write without having to wait for it: a client always reads its
+    /// own writes.  This doesn't hold across separate clients, which may observe the write later.
+    ///
+    /// As there is no reply, an error storing the value, such as the value being too large, is
+    /// only reported by a later [`Client::sync`].
+    pub async fn set_no_reply<K, V>(
         &mut self,
         key: K,
         value: V,
@@ -302,355 +1473,3430 @@ impl Client {
         K: AsRef<[u8]>,
         V: AsMemcachedValue,
     {
-        let kr = key.as_ref();
         let vr = value.as_bytes();
 
-        self.conn.write_all(b"add ").await?;
-        self.conn.write_all(kr).await?;
-
-        let flags = flags.unwrap_or(0).to_string();
-        self.conn.write_all(b" ").await?;
-        self.conn.write_all(flags.as_ref()).await?;
+        self.write_storage_command_no_reply(b"set", key.as_ref(), vr.as_ref(), ttl, flags)
+            .await
+    }
 
-        let ttl = ttl.unwrap_or(0).to_string();
-        self.conn.write_all(b" ").await?;
-        self.conn.write_all(ttl.as_ref()).await?;
+    /// Sets the given key, streaming exactly `len` bytes of its value from `reader`.
+    ///
+    /// The value is copied directly from `reader` to the connection, so it never needs to be held
+    /// in memory in its entirety.  If `ttl` or `flags` are not specified, they default as for
+    /// [`Client::set`].
+    ///
+    /// If `reader` ends before `len` bytes have been read, an [`Error::Transport`] with a kind of
+    /// `UnexpectedEof` is returned.  If this happens, or reading from `reader` or writing to the
+    /// connection fails, or the command is cancelled, once the command has started to be written,
+    /// the server is still waiting for the rest of the value, so the connection can no longer be
+    /// used and the client is poisoned.
+    pub async fn set_reader<K, R>(
+        &mut self,
+        key: K,
+        reader: R,
+        len: u64,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        R: AsyncRead + Unpin,
+    {
+        self.check_poisoned()?;
+        let header = self.storage_header(b"set", key.as_ref(), len, ttl, flags);
 
-        let vlen = vr.len().to_string();
-        self.conn.write_all(b" ").await?;
-        self.conn.write_all(vlen.as_ref()).await?;
-        self.conn.write_all(b"\r\n").await?;
+        // Whatever follows the header is read by the server as the value, so stay poisoned until
+        // all of it has been sent.
+        self.poisoned = true;
+        self.conn.write_all(&header).await?;
 
-        self.conn.write_all(vr.as_ref()).await?;
+        let copied = tokio::io::copy(&mut reader.take(len), &mut self.conn).await?;
+        if copied != len {
+            return Err(Error::Transport(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("expected {} bytes from reader, got {}", len, copied),
+            )));
+        }
         self.conn.write_all(b"\r\n").await?;
 
         self.conn.flush().await?;
+        self.poisoned = false;
 
         match self.get_read_write_response().await? {
             Response::Status(Status::Stored) => Ok(()),
             Response::Status(s) => Err(s.into()),
-            _ => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+            r => Err(Status::Error(r.unexpected("set")).into()),
         }
     }
 
-    /// Attempts to add multiple keys and values through pipelined commands.
+    /// Sets the given key, with a value made up of the given chunks.
     ///
-    /// If `ttl` or `flags` are not specified, they will default to 0. The same values for `ttl` and `flags` will be applied to each key.
-    /// Returns a result with a HashMap of keys mapped to the result of the add operation, or an error.
-    pub async fn add_multi<'a, K, V>(
+    /// The chunks are written one after another, so a value held in fragments, such as a rope or
+    /// a framed body, never needs to be concatenated into a single buffer.  `total_len` must be
+    /// the combined length of the chunks, otherwise [`Error::Transport`] with
+    /// [`std::io::ErrorKind::InvalidInput`] is returned before anything is written, and the
+    /// client remains usable.  If `ttl` or `flags` are not specified, they default as for
+    /// [`Client::set`].
+    pub async fn set_chunked<K, I, C>(
         &mut self,
-        kv: &'a [(K, V)],
+        key: K,
+        chunks: I,
+        total_len: u64,
         ttl: Option<i64>,
         flags: Option<u32>,
-    ) -> Result<FxHashMap<&'a K, Result<(), Error>>, Error>
+    ) -> Result<(), Error>
     where
-        K: AsRef<[u8]> + Eq + std::hash::Hash + std::fmt::Debug,
-        V: AsMemcachedValue,
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = C>,
+        C: AsRef<[u8]>,
     {
-        for (key, value) in kv {
-            let kr = key.as_ref();
-            let vr = value.as_bytes();
-
-            self.conn.write_all(b"add ").await?;
-            self.conn.write_all(kr).await?;
-
-            let flags = flags.unwrap_or(0).to_string();
-            self.conn.write_all(b" ").await?;
-            self.conn.write_all(flags.as_ref()).await?;
-
-            let ttl = ttl.unwrap_or(0).to_string();
-            self.conn.write_all(b" ").await?;
-            self.conn.write_all(ttl.as_ref()).await?;
-
-            let vlen = vr.len().to_string();
-            self.conn.write_all(b" ").await?;
-            self.conn.write_all(vlen.as_ref()).await?;
-            self.conn.write_all(b"\r\n").await?;
-
-            self.conn.write_all(vr.as_ref()).await?;
-            self.conn.write_all(b"\r\n").await?;
+        let chunks: Vec<C> = chunks.into_iter().collect();
+        let len: u64 = chunks.iter().map(|c| c.as_ref().len() as u64).sum();
+        if len != total_len {
+            return Err(Error::Transport(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "chunks add up to {} bytes, but the total length is {}",
+                    len, total_len
+                ),
+            )));
         }
-        self.conn.flush().await?;
 
-        let results = self.map_set_multi_responses(kv).await?;
+        let header = self.storage_header(b"set", key.as_ref(), total_len, ttl, flags);
+        let mut parts: Vec<&[u8]> = Vec::with_capacity(chunks.len() + 2);
+        parts.push(&header);
+        parts.extend(chunks.iter().map(|c| c.as_ref()));
+        parts.push(b"\r\n");
+        self.write_all_vectored(&parts).await?;
+        self.conn.flush().await?;
 
-        Ok(results)
+        match self.get_read_write_response().await? {
+            Response::Status(Status::Stored) => Ok(()),
+            Response::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("set")).into()),
+        }
     }
 
-    /// Delete a key but don't wait for a reply.
-    pub async fn delete_no_reply<K>(&mut self, key: K) -> Result<(), Error>
+    /// Sets the given key, only if its current flags match `expected_flags`.
+    ///
+    /// This is useful for migrations where flags mark the version of the schema a value was
+    /// written with, so that only values written by an old version are overwritten.  The key's
+    /// flags and CAS identifier are read with a meta get, and the value is then stored with a meta
+    /// set conditional on that CAS identifier, with `new_flags` and `ttl`.  If `ttl` is not
+    /// specified, it defaults as for [`Client::set`].
+    ///
+    /// If the key does not exist, [`CommandError::NotFound`] is returned, and if its flags differ,
+    /// [`CommandError::FlagsMismatch`] is returned.  If the key was changed by another client
+    /// between the two commands, [`CommandError::Exists`] is returned.  Requires support for meta
+    /// commands, available as of memcached 1.6.0.
+    pub async fn set_if_flags<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        expected_flags: u32,
+        ttl: Option<i64>,
+        new_flags: u32,
+    ) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
         let kr = key.as_ref();
 
-        self.conn
-            .write_all(&[b"delete ", kr, b" noreply\r\n"].concat())
+        let current = self
+            .meta_get(kr, &GetFlags::new().want_flags().want_cas())
+            .await?
+            .ok_or(Status::NotFound)?;
+        let actual = current.flags.unwrap_or(0);
+        if actual != expected_flags {
+            return Err(CommandError::FlagsMismatch {
+                expected: expected_flags,
+                actual,
+            }
+            .into());
+        }
+        let cas = current.cas.ok_or_else(|| {
+            Status::Error(ErrorKind::Protocol(Some(
+                "missing CAS identifier in response to mg".to_string(),
+            )))
+        })?;
+
+        let meta_flags = StoreFlags::new()
+            .compare_cas(cas)
+            .set_ttl(self.storage_ttl(ttl))
+            .set_flags(new_flags);
+        self.meta_store(kr, value, StoreMode::Set, &meta_flags)
             .await?;
-        self.conn.flush().await?;
+
         Ok(())
     }
 
-    /// Delete a key and wait for a reply
-    pub async fn delete<K>(&mut self, key: K) -> Result<(), Error>
+    /// Sets the given key, returning the CAS identifier of the newly stored value.
+    ///
+    /// If `ttl` or `flags` are not specified, they default as for [`Client::set`].  This uses a
+    /// meta set command, so the CAS identifier is returned in the same round trip.  On servers
+    /// without support for meta commands (prior to memcached 1.6.0), this falls back to a `set`
+    /// followed by a `gets`, in which case the key could be changed by another client in between
+    /// the two.
+    pub async fn set_returning_cas<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<u64, Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
         let kr = key.as_ref();
 
-        self.conn
-            .write_all(&[b"delete ", kr, b"\r\n"].concat())
-            .await?;
-        self.conn.flush().await?;
+        if !self.supports_meta().await? {
+            self.set(kr, value, ttl, flags).await?;
 
-        match self.get_read_write_response().await? {
-            Response::Status(Status::Deleted) => Ok(()),
-            Response::Status(s) => Err(s.into()),
-            _ => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+            self.write_all_vectored(&[b"gets ", kr, b"\r\n"]).await?;
+            self.conn.flush().await?;
+
+            return match self.get_response().await? {
+                Some(value) => value.cas.ok_or_else(|| {
+                    Status::Error(ErrorKind::Protocol(Some(
+                        "missing CAS identifier in response to gets".to_string(),
+                    )))
+                    .into()
+                }),
+                None => Err(Status::NotFound.into()),
+            };
         }
+
+        let meta_flags = StoreFlags::new()
+            .want_cas()
+            .set_ttl(self.storage_ttl(ttl))
+            .set_flags(self.storage_flags(flags));
+
+        self.meta_store(kr, value, StoreMode::Set, &meta_flags)
+            .await?
+            .cas
+            .ok_or_else(|| {
+                Status::Error(ErrorKind::Protocol(Some(
+                    "missing CAS identifier in response to ms".to_string(),
+                )))
+                .into()
+            })
     }
 
-    /// Delete multiple keys
-    pub async fn delete_multi_no_reply<K>(&mut self, keys: &[K]) -> Result<(), Error>
+    /// Stores the given value through the meta protocol, with the semantics chosen by `mode`.
+    ///
+    /// This covers each of the classic storage commands, while also supporting any applicable
+    /// flags: for example, [`StoreFlags::set_ttl`] and [`StoreFlags::set_flags`] set the TTL and
+    /// flags of the key, [`StoreFlags::compare_cas`] only stores the value if the key's CAS
+    /// identifier matches, and [`StoreFlags::want_cas`] returns the CAS identifier of the stored
+    /// value.  The modes map onto the `M` flag as follows: [`StoreMode::Set`] is `MS`,
+    /// [`StoreMode::Add`] is `ME`, [`StoreMode::Replace`] is `MR`, [`StoreMode::Append`] is `MA`
+    /// and [`StoreMode::Prepend`] is `MP`.
+    ///
+    /// If the value is stored, a [`MetaValue`] is returned with any fields requested through
+    /// `flags`.  If it was not stored, [`Error`] is returned with [`CommandError::NotStored`] if
+    /// the mode's condition on the key was not met, [`CommandError::Exists`] if the CAS identifier
+    /// did not match, or [`CommandError::NotFound`] if a CAS identifier was given for a key which
+    /// does not exist.
+    ///
+    /// Available as of memcached 1.6.0.  For older servers, use the classic storage commands.
+    pub async fn meta_store<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        mode: StoreMode,
+        flags: &StoreFlags,
+    ) -> Result<MetaValue, Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
-        for key in keys {
-            self.conn.write_all(b"delete ").await?;
-            self.conn.write_all(key.as_ref()).await?;
-            self.conn.write_all(b" noreply\r\n").await?;
-        }
+        let vr = value.as_bytes();
+
+        let (key, flags) = flags.0.encode_key(key.as_ref());
+
+        let mut bf = Vec::new();
+        bf.extend(b"ms ");
+        bf.extend(key.as_ref());
+        bf.extend(b" ");
+        bf.extend(vr.len().to_string().as_bytes());
+        flags.write_tokens(&mut bf);
+        bf.extend(b" ");
+        bf.extend(mode.token());
+        bf.extend(b"\r\n");
+        self.write_all_vectored(&[&bf, vr.as_ref(), b"\r\n"])
+            .await?;
         self.conn.flush().await?;
 
-        Ok(())
+        match self.get_meta_response().await? {
+            MetaResponse::Header(tokens) => Ok(MetaValue::from_tokens(&tokens, None)),
+            MetaResponse::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("ms")).into()),
+        }
     }
 
-    /// Increments the given key by the specified amount.
-    /// Can overflow from the max value of u64 (18446744073709551615) -> 0.
-    /// If the key does not exist, the server will return a KeyNotFound error.
-    /// If the key exists but the value is non-numeric, the server will return a ClientError.
-    pub async fn increment<K>(&mut self, key: K, amount: u64) -> Result<u64, Error>
+    /// Sets the key written by `write_key`.
+    ///
+    /// `write_key` is given a buffer owned by this client, which is cleared and reused across
+    /// calls, so keys can be formatted without allocating.  Otherwise, this behaves exactly like
+    /// [`Client::set`].
+    pub async fn set_with_key_writer<F, V>(
+        &mut self,
+        write_key: F,
+        value: V,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Vec<u8>),
+        V: AsMemcachedValue,
+    {
+        let mut key = std::mem::take(&mut self.key_buf);
+        key.clear();
+        write_key(&mut key);
+
+        let result = self.set(&key, value, ttl, flags).await;
+        self.key_buf = key;
+        result
+    }
+
+    /// Sets multiple keys and values through pipelined commands.
+    ///
+    /// If `ttl` or `flags` are not specified, they default as for [`Client::set`].  The same
+    /// values for `ttl` and `flags` are applied to each key.
+    /// Returns a result with a HashMap of keys mapped to the result of the set operation, or an error.
+    ///
+    /// The result for each key is `Ok(())` if it was stored, or otherwise the error for that key,
+    /// such as [`CommandError::NotStored`], or [`CommandError::Error`] with [`ErrorKind::Server`]
+    /// if the value was rejected.
+    pub async fn set_multi<'a, K, V>(
+        &mut self,
+        kv: &'a [(K, V)],
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<FxHashMap<&'a K, Result<(), Error>>, Error>
+    where
+        K: AsRef<[u8]> + Eq + std::hash::Hash + std::fmt::Debug,
+        V: AsMemcachedValue,
+    {
+        if kv.is_empty() {
+            return Ok(FxHashMap::default());
+        }
+
+        for (key, value) in kv {
+            let kr = key.as_ref();
+            let vr = value.as_bytes();
+
+            let header = self.storage_header(b"set", kr, vr.len() as u64, ttl, flags);
+            self.write_all_vectored(&[&header, vr.as_ref(), b"\r\n"])
+                .await?;
+        }
+        self.conn.flush().await?;
+
+        let results = self.map_set_multi_responses(kv, "set").await?;
+
+        Ok(results)
+    }
+
+    /// Appends the given value to the data of an existing key.
+    ///
+    /// The flags and TTL of the key are kept.  If the key does not exist, `Err(Command(NotStored))`
+    /// is returned, and [`CommandError::ValueTooLarge`] is returned if the combined value would
+    /// exceed the server's maximum item size.  Otherwise, [`Error`] is returned.
+    /// [`Client::try_append`] returns whether the key existed instead.
+    pub async fn append<K, V>(&mut self, key: K, value: V) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
-        self.conn
-            .write_all(
-                &[
-                    b"incr ",
-                    key.as_ref(),
-                    b" ",
-                    amount.to_string().as_bytes(),
-                    b"\r\n",
-                ]
-                .concat(),
-            )
+        if self.try_append(key, value).await? {
+            Ok(())
+        } else {
+            Err(Status::NotStored.into())
+        }
+    }
+
+    /// Prepends the given value to the data of an existing key.
+    ///
+    /// This behaves exactly like [`Client::append`], except that the value is added before the
+    /// existing data.  [`Client::try_prepend`] returns whether the key existed instead.
+    pub async fn prepend<K, V>(&mut self, key: K, value: V) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsMemcachedValue,
+    {
+        if self.try_prepend(key, value).await? {
+            Ok(())
+        } else {
+            Err(Status::NotStored.into())
+        }
+    }
+
+    /// Appends the given value to the data of an existing key.
+    ///
+    /// Returns `true` if the value was appended, and `false` if the key does not exist.  If the
+    /// server rejects the combined value with `object too large for cache`,
+    /// [`CommandError::ValueTooLarge`] is returned.  Other server errors, including `out of memory
+    /// storing object`, are returned as [`ErrorKind::Server`].
+    ///
+    /// When building up a log or list in a single key, [`CommandError::ValueTooLarge`] signals that
+    /// it is time to roll over to a new key, e.g. by appending a sequence number to the key and
+    /// storing the value under the next one with [`Client::add`].
+    pub async fn try_append<K, V>(&mut self, key: K, value: V) -> Result<bool, Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsMemcachedValue,
+    {
+        self.try_concat("append", key.as_ref(), value.as_bytes().as_ref())
+            .await
+    }
+
+    /// Prepends the given value to the data of an existing key.
+    ///
+    /// Returns `true` if the value was prepended, and `false` if the key does not exist.  As with
+    /// [`Client::try_append`], [`CommandError::ValueTooLarge`] is returned if the server rejects
+    /// the combined value with `object too large for cache`.  Otherwise, [`Error`] is returned.
+    pub async fn try_prepend<K, V>(&mut self, key: K, value: V) -> Result<bool, Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsMemcachedValue,
+    {
+        self.try_concat("prepend", key.as_ref(), value.as_bytes().as_ref())
+            .await
+    }
+
+    async fn try_concat(&mut self, command: &str, key: &[u8], value: &[u8]) -> Result<bool, Error> {
+        // The flags and TTL of the existing key are kept, so these are ignored by the server.
+        self.write_storage_command(command.as_bytes(), key, value, None, None)
             .await?;
         self.conn.flush().await?;
 
         match self.get_read_write_response().await? {
+            Response::Status(Status::Stored) => Ok(true),
+            Response::Status(Status::NotStored) => Ok(false),
+            Response::Status(Status::Error(ErrorKind::Server(msg))) if is_too_large_error(&msg) => {
+                Err(CommandError::ValueTooLarge(msg).into())
+            }
             Response::Status(s) => Err(s.into()),
-            Response::IncrDecr(amount) => Ok(amount),
-            _ => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+            r => Err(Status::Error(r.unexpected(command)).into()),
         }
     }
 
-    /// Increments the given key by the specified amount with no reply from the server.
-    /// Can overflow from the max value of u64 (18446744073709551615) -> 0.
-    /// Always returns () for a complete request, will not return any indication of success or failure.
-    pub async fn increment_no_reply<K>(&mut self, key: K, amount: u64) -> Result<(), Error>
+    /// Add a key. If the value exists, Err(Command(NotStored)) is returned.
+    pub async fn add<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
-        self.conn
-            .write_all(
-                &[
-                    b"incr ",
-                    key.as_ref(),
-                    b" ",
-                    amount.to_string().as_bytes(),
-                    b" noreply\r\n",
-                ]
-                .concat(),
-            )
+        let kr = key.as_ref();
+        let vr = value.as_bytes();
+
+        self.write_storage_command(b"add", kr, vr.as_ref(), ttl, flags)
             .await?;
         self.conn.flush().await?;
 
-        Ok(())
+        match self.get_read_write_response().await? {
+            Response::Status(Status::Stored) => Ok(()),
+            Response::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("add")).into()),
+        }
     }
 
-    /// Decrements the given key by the specified amount.
-    /// Will not decrement the counter below 0.
-    /// If the key does not exist, the server will return a KeyNotFound error.
-    /// If the key exists but the value is non-numeric, the server will return a ClientError.
-    pub async fn decrement<K>(&mut self, key: K, amount: u64) -> Result<u64, Error>
+    /// Replaces the value of an existing key.
+    ///
+    /// If `ttl` or `flags` are not specified, they default as for [`Client::set`].  If the key
+    /// does not exist, `Err(Command(NotStored))` is returned, as the complement of
+    /// [`Client::add`].  Otherwise, [`Error`] is returned if the value could not be stored.
+    pub async fn replace<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
-        self.conn
-            .write_all(
-                &[
-                    b"decr ",
-                    key.as_ref(),
-                    b" ",
-                    amount.to_string().as_bytes(),
-                    b"\r\n",
-                ]
-                .concat(),
-            )
+        let kr = key.as_ref();
+        let vr = value.as_bytes();
+
+        self.write_storage_command(b"replace", kr, vr.as_ref(), ttl, flags)
             .await?;
         self.conn.flush().await?;
 
         match self.get_read_write_response().await? {
+            Response::Status(Status::Stored) => Ok(()),
             Response::Status(s) => Err(s.into()),
-            Response::IncrDecr(amount) => Ok(amount),
-            _ => Err(Status::Error(ErrorKind::Protocol(None)).into()),
+            r => Err(Status::Error(r.unexpected("replace")).into()),
         }
     }
 
-    /// Decrements the given key by the specified amount with no reply from the server.
-    /// Will not decrement the counter below 0.
-    /// Always returns () for a complete request, will not return any indication of success or failure.
-    pub async fn decrement_no_reply<K>(&mut self, key: K, amount: u64) -> Result<(), Error>
+    /// Replaces the value of an existing key but doesn't wait for a reply.
+    ///
+    /// As with [`Client::set_no_reply`], whether the key existed, and so whether the value was
+    /// stored, is never reported.
+    pub async fn replace_no_reply<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<(), Error>
     where
         K: AsRef<[u8]>,
+        V: AsMemcachedValue,
     {
-        self.conn
-            .write_all(
-                &[
-                    b"decr ",
-                    key.as_ref(),
-                    b" ",
-                    amount.to_string().as_bytes(),
-                    b" noreply\r\n",
-                ]
-                .concat(),
-            )
-            .await?;
-        self.conn.flush().await?;
+        let vr = value.as_bytes();
 
-        Ok(())
+        self.write_storage_command_no_reply(b"replace", key.as_ref(), vr.as_ref(), ttl, flags)
+            .await
     }
 
-    /// Gets the version of the server.
+    /// Attempts to add multiple keys and values through pipelined commands.
     ///
-    /// If the version is retrieved successfully, `String` is returned containing the version
-    /// component e.g. `1.6.7`, otherwise [`Error`] is returned.
+    /// If `ttl` or `flags` are not specified, they default as for [`Client::set`].  The same
+    /// values for `ttl` and `flags` are applied to each key.
+    /// Returns a result with a HashMap of keys mapped to the result of the add operation, or an error.
     ///
-    /// For some setups, such as those using Twemproxy, this will return an error as those
-    /// intermediate proxies do not support the version command.
-    pub async fn version(&mut self) -> Result<String, Error> {
-        self.conn.write_all(b"version\r\n").await?;
-        self.conn.flush().await?;
+    /// The result for each key is `Ok(())` if it was added, or otherwise the error for that key,
+    /// which is [`CommandError::NotStored`] if the key already exists.
+    pub async fn add_multi<'a, K, V>(
+        &mut self,
+        kv: &'a [(K, V)],
+        ttl: Option<i64>,
+        flags: Option<u32>,
+    ) -> Result<FxHashMap<&'a K, Result<(), Error>>, Error>
+    where
+        K: AsRef<[u8]> + Eq + std::hash::Hash + std::fmt::Debug,
+        V: AsMemcachedValue,
+    {
+        if kv.is_empty() {
+            return Ok(FxHashMap::default());
+        }
 
-        let mut version = String::new();
-        let bytes = self.conn.read_line(&mut version).await?;
+        for (key, value) in kv {
+            let kr = key.as_ref();
+            let vr = value.as_bytes();
 
-        // Peel off the leading "VERSION " header.
-        if bytes >= 8 && version.is_char_boundary(8) {
-            Ok(version.split_off(8))
-        } else {
-            Err(Error::from(Status::Error(ErrorKind::Protocol(Some(
-                format!("Invalid response for `version` command: `{version}`"),
-            )))))
+            let header = self.storage_header(b"add", kr, vr.len() as u64, ttl, flags);
+            self.write_all_vectored(&[&header, vr.as_ref(), b"\r\n"])
+                .await?;
         }
+        self.conn.flush().await?;
+
+        let results = self.map_set_multi_responses(kv, "add").await?;
+
+        Ok(results)
     }
 
-    /// Dumps all keys from the server.
-    ///
-    /// This operation scans all slab classes from tail to head, in a non-blocking fashion.  Thus,
-    /// not all items will be found as new items could be inserted or deleted while the crawler is
-    /// still running.
-    ///
-    /// [`MetadumpIter`] must be iterated over to discover whether or not the crawler successfully
-    /// started, as this call will only return [`Error`] if the command failed to be written to the
-    /// server at all.
-    ///
-    /// Available as of memcached 1.4.31.
-    pub async fn dump_keys(&mut self) -> Result<MetadumpIter<'_>, Error> {
-        self.conn.write_all(b"lru_crawler metadump all\r\n").await?;
-        self.conn.flush().await?;
+    /// Delete a key but don't wait for a reply.
+    pub async fn delete_no_reply<K>(&mut self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let kr = key.as_ref();
 
-        Ok(MetadumpIter {
-            client: self,
-            done: false,
-        })
+        self.write_all_vectored(&[b"delete ", kr, b" noreply\r\n"])
+            .await?;
+        self.conn.flush().await?;
+        Ok(())
     }
 
-    /// Collects statistics from the server.
-    ///
-    /// The statistics that may be returned are detailed in the protocol specification for
-    /// memcached, but all values returned by this method are returned as strings and are not
-    /// further interpreted or validated for conformity.
-    pub async fn stats(&mut self) -> Result<FxHashMap<String, String>, Error> {
-        let mut entries = FxHashMap::default();
+    /// Delete a key and wait for a reply
+    pub async fn delete<K>(&mut self, key: K) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let kr = key.as_ref();
 
-        self.conn.write_all(b"stats\r\n").await?;
+        self.write_all_vectored(&[b"delete ", kr, b"\r\n"]).await?;
         self.conn.flush().await?;
 
-        while let StatsResponse::Entry(key, value) = self.get_stats_response().await? {
-            entries.insert(key, value);
+        match self.get_read_write_response().await? {
+            Response::Status(Status::Deleted) => Ok(()),
+            Response::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("delete")).into()),
         }
-
-        Ok(entries)
     }
 
-    /// Flushes all existing items on the server
+    /// Updates the TTL of an existing key, without fetching or changing its data.
     ///
-    /// This operation invalidates all existing items immediately. Any items with an update time
-    /// older than the time of the flush_all operation will be ignored for retrieval purposes.
-    /// This operation does not free up memory taken up by the existing items.
-
-    pub async fn flush_all(&mut self) -> Result<(), Error> {
-        self.conn.write_all(b"flush_all\r\n").await?;
-        self.conn.flush().await?;
-
-        let mut response = String::new();
-        self.conn.read_line(&mut response).await?;
-        // check if response is ok
-        if response.trim() == "OK" {
+    /// If the key does not exist, `Err(Command(NotFound))` is returned.  Otherwise, [`Error`] is
+    /// returned if the command fails.
+    pub async fn touch<K>(&mut self, key: K, ttl: i64) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        if self.touch_if_exists(key, ttl).await? {
             Ok(())
         } else {
-            Err(Error::from(Status::Error(ErrorKind::Protocol(Some(
-                format!("Invalid response for `flush_all` command: `{response}`"),
-            )))))
+            Err(Status::NotFound.into())
         }
     }
-}
-
-/// Asynchronous iterator for metadump operations.
-pub struct MetadumpIter<'a> {
-    client: &'a mut Client,
-    done: bool,
-}
 
-impl<'a> MetadumpIter<'a> {
-    /// Gets the next result for the current operation.
+    /// Updates the TTL of a key but doesn't wait for a reply.
     ///
-    /// If there is another key in the dump, `Some(Ok(KeyMetadata))` will be returned.  If there was
-    /// an error while attempting to start the metadump operation, or if there was a general
-    /// network/protocol-level error, `Some(Err(Error))` will be returned.
+    /// Whether the key existed is never reported, so this suits fire-and-forget refreshes of
+    /// expiry.
+    pub async fn touch_no_reply<K>(&mut self, key: K, ttl: i64) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.write_all_vectored(&[
+            b"touch ",
+            key.as_ref(),
+            b" ",
+            ttl.to_string().as_bytes(),
+            b" noreply\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+        Ok(())
+    }
+
+    /// Updates the TTL of a key if it exists.
     ///
-    /// Otherwise, `None` will be returned and signals the end of the iterator.  Subsequent calls
-    /// will return `None`.
-    pub async fn next(&mut self) -> Option<Result<KeyMetadata, Error>> {
-        if self.done {
-            return None;
+    /// Returns `true` if the key was touched, and `false` if the key does not exist.  Otherwise,
+    /// [`Error`] is returned.  This suits sliding expiration, where a key is refreshed if present
+    /// and left alone once it has expired.
+    pub async fn touch_if_exists<K>(&mut self, key: K, ttl: i64) -> Result<bool, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.write_all_vectored(&[
+            b"touch ",
+            key.as_ref(),
+            b" ",
+            ttl.to_string().as_bytes(),
+            b"\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+
+        match self.get_read_write_response().await? {
+            Response::Status(Status::Touched) => Ok(true),
+            Response::Status(Status::NotFound) => Ok(false),
+            Response::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("touch")).into()),
         }
+    }
 
-        match self.client.get_metadump_response().await {
-            Ok(MetadumpResponse::End) => {
-                self.done = true;
+    /// Deletes multiple keys through pipelined commands.
+    ///
+    /// All of the `delete` commands are written and flushed at once, and their replies are then
+    /// read in order, so each reply is matched to a key by its position, in the same order as the
+    /// keys were given.  Returns a map holding only the keys which were not deleted, each mapped to
+    /// its error, such as [`CommandError::NotFound`]; it is empty if every key was deleted.
+    /// Otherwise, [`Error`] is returned.
+    pub async fn delete_multi<I, K>(
+        &mut self,
+        keys: I,
+    ) -> Result<HashMap<K, Result<(), Error>>, Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]> + Eq + std::hash::Hash,
+    {
+        let keys: Vec<K> = keys.into_iter().collect();
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        for key in &keys {
+            self.write_all_vectored(&[b"delete ", key.as_ref(), b"\r\n"])
+                .await?;
+        }
+        self.conn.flush().await?;
+
+        let mut results = HashMap::with_capacity(keys.len());
+        for key in keys {
+            let result = match self.get_read_write_response().await? {
+                Response::Status(Status::Deleted) => continue,
+                Response::Status(s) => Err(s.into()),
+                r => Err(Status::Error(r.unexpected("delete")).into()),
+            };
+
+            results.insert(key, result);
+        }
+
+        Ok(results)
+    }
+
+    /// Updates the TTL of multiple keys through pipelined commands.
+    ///
+    /// All of the `touch` commands are written and flushed at once, and their replies are then read
+    /// in order, matching each to a key by its position.  Returns a map holding only the keys which
+    /// were not touched: `Ok(false)` if the key does not exist, or the error for that key.  It is
+    /// empty if every key was touched.  Otherwise, [`Error`] is returned.
+    pub async fn touch_multi<I, K>(
+        &mut self,
+        keys: I,
+        ttl: i64,
+    ) -> Result<HashMap<K, Result<bool, Error>>, Error>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]> + Eq + std::hash::Hash,
+    {
+        let keys: Vec<K> = keys.into_iter().collect();
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let ttl = ttl.to_string();
+        for key in &keys {
+            self.write_all_vectored(&[b"touch ", key.as_ref(), b" ", ttl.as_bytes(), b"\r\n"])
+                .await?;
+        }
+        self.conn.flush().await?;
+
+        let mut results = HashMap::with_capacity(keys.len());
+        for key in keys {
+            let result = match self.get_read_write_response().await? {
+                Response::Status(Status::Touched) => continue,
+                Response::Status(Status::NotFound) => Ok(false),
+                Response::Status(s) => Err(s.into()),
+                r => Err(Status::Error(r.unexpected("touch")).into()),
+            };
+
+            results.insert(key, result);
+        }
+
+        Ok(results)
+    }
+
+    /// Delete multiple keys
+    pub async fn delete_multi_no_reply<K>(&mut self, keys: &[K]) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        for key in keys {
+            self.write_all_vectored(&[b"delete ", key.as_ref(), b" noreply\r\n"])
+                .await?;
+        }
+        self.conn.flush().await?;
+
+        Ok(())
+    }
+
+    /// Increments the given key by the specified amount.
+    /// Can overflow from the max value of u64 (18446744073709551615) -> 0.
+    /// If the key does not exist, the server will return a KeyNotFound error.
+    /// If the key exists but the value is non-numeric, the server will return a ClientError.
+    pub async fn increment<K>(&mut self, key: K, amount: u64) -> Result<u64, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.write_all_vectored(&[
+            b"incr ",
+            key.as_ref(),
+            b" ",
+            amount.to_string().as_bytes(),
+            b"\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+
+        match self.get_read_write_response().await? {
+            Response::Status(s) => Err(s.into()),
+            Response::IncrDecr(amount) => Ok(amount),
+            r => Err(Status::Error(r.unexpected("incr")).into()),
+        }
+    }
+
+    /// Increments the given key by the specified amount, returning the new value as the raw bytes
+    /// sent by the server.
+    ///
+    /// Unlike [`Client::increment`], the value is not parsed as a `u64`, so callers with unusual
+    /// counter semantics can handle it themselves.  Errors are classified in the same way as for
+    /// [`Client::increment`].
+    pub async fn increment_raw<K>(&mut self, key: K, amount: u64) -> Result<Vec<u8>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.incr_decr_raw("incr", key.as_ref(), amount).await
+    }
+
+    /// Increments multiple keys, each by its own amount, through pipelined commands.
+    ///
+    /// Returns a map of each key to the result of its increment, which is the new value of the
+    /// counter, or an error classified in the same way as for [`Client::increment`]: a missing key
+    /// is [`CommandError::NotFound`], and a non-numeric value is a [`ErrorKind::Client`] error.  As
+    /// with [`Client::increment`], counters wrap around on overflow rather than returning an error.
+    /// If a key is given more than once, the result of its last increment is returned.
+    pub async fn increment_multi<I, K>(
+        &mut self,
+        items: I,
+    ) -> Result<FxHashMap<K, Result<u64, Error>>, Error>
+    where
+        I: IntoIterator<Item = (K, u64)>,
+        K: AsRef<[u8]> + Eq + std::hash::Hash,
+    {
+        self.incr_decr_multi("incr", items).await
+    }
+
+    /// Decrements multiple keys, each by its own amount, through pipelined commands.
+    ///
+    /// Returns a map of each key to the result of its decrement, classified in the same way as for
+    /// [`Client::increment_multi`].  As with [`Client::decrement`], counters will not be
+    /// decremented below 0.
+    pub async fn decrement_multi<I, K>(
+        &mut self,
+        items: I,
+    ) -> Result<FxHashMap<K, Result<u64, Error>>, Error>
+    where
+        I: IntoIterator<Item = (K, u64)>,
+        K: AsRef<[u8]> + Eq + std::hash::Hash,
+    {
+        self.incr_decr_multi("decr", items).await
+    }
+
+    async fn incr_decr_multi<I, K>(
+        &mut self,
+        command: &str,
+        items: I,
+    ) -> Result<FxHashMap<K, Result<u64, Error>>, Error>
+    where
+        I: IntoIterator<Item = (K, u64)>,
+        K: AsRef<[u8]> + Eq + std::hash::Hash,
+    {
+        let items: Vec<(K, u64)> = items.into_iter().collect();
+        if items.is_empty() {
+            return Ok(FxHashMap::default());
+        }
+
+        let mut bf = Vec::new();
+        for (key, amount) in &items {
+            bf.extend(command.as_bytes());
+            bf.extend(b" ");
+            bf.extend(key.as_ref());
+            bf.extend(b" ");
+            bf.extend(amount.to_string().as_bytes());
+            bf.extend(b"\r\n");
+        }
+        self.conn.write_all(&bf).await?;
+        self.conn.flush().await?;
+
+        let mut results = FxHashMap::with_capacity_and_hasher(items.len(), Default::default());
+        for (key, _) in items {
+            let result = match self.get_read_write_response().await? {
+                Response::IncrDecr(value) => Ok(value),
+                Response::Status(s) => Err(s.into()),
+                r => Err(Status::Error(r.unexpected(command)).into()),
+            };
+            results.insert(key, result);
+        }
+
+        Ok(results)
+    }
+
+    /// Increments the given key by the specified amount with no reply from the server.
+    /// Can overflow from the max value of u64 (18446744073709551615) -> 0.
+    /// Always returns () for a complete request, will not return any indication of success or failure.
+    pub async fn increment_no_reply<K>(&mut self, key: K, amount: u64) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.write_all_vectored(&[
+            b"incr ",
+            key.as_ref(),
+            b" ",
+            amount.to_string().as_bytes(),
+            b" noreply\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+
+        Ok(())
+    }
+
+    /// Decrements the given key by the specified amount.
+    /// Will not decrement the counter below 0.
+    /// If the key does not exist, the server will return a KeyNotFound error.
+    /// If the key exists but the value is non-numeric, the server will return a ClientError.
+    pub async fn decrement<K>(&mut self, key: K, amount: u64) -> Result<u64, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.write_all_vectored(&[
+            b"decr ",
+            key.as_ref(),
+            b" ",
+            amount.to_string().as_bytes(),
+            b"\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+
+        match self.get_read_write_response().await? {
+            Response::Status(s) => Err(s.into()),
+            Response::IncrDecr(amount) => Ok(amount),
+            r => Err(Status::Error(r.unexpected("decr")).into()),
+        }
+    }
+
+    /// Decrements the given key by the specified amount, returning the new value as the raw bytes
+    /// sent by the server.
+    ///
+    /// See [`Client::increment_raw`].
+    pub async fn decrement_raw<K>(&mut self, key: K, amount: u64) -> Result<Vec<u8>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.incr_decr_raw("decr", key.as_ref(), amount).await
+    }
+
+    async fn incr_decr_raw(
+        &mut self,
+        command: &str,
+        key: &[u8],
+        amount: u64,
+    ) -> Result<Vec<u8>, Error> {
+        self.write_all_vectored(&[
+            command.as_bytes(),
+            b" ",
+            key,
+            b" ",
+            amount.to_string().as_bytes(),
+            b"\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+
+        let line = self.drive_receive(parse_ascii_line).await?;
+        if line == b"NOT_FOUND" {
+            return Err(Status::NotFound.into());
+        }
+        match error_line_kind(&line) {
+            Some(ErrorKind::Client(msg)) if is_desync_error(&msg) => Err(self.desync(msg)),
+            Some(ErrorKind::BadCommandFormat) => Err(self.bad_command_format()),
+            Some(kind) => Err(Status::Error(kind).into()),
+            None => Ok(line),
+        }
+    }
+
+    /// Decrements the given key by the specified amount with no reply from the server.
+    /// Will not decrement the counter below 0.
+    /// Always returns () for a complete request, will not return any indication of success or failure.
+    pub async fn decrement_no_reply<K>(&mut self, key: K, amount: u64) -> Result<(), Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.write_all_vectored(&[
+            b"decr ",
+            key.as_ref(),
+            b" ",
+            amount.to_string().as_bytes(),
+            b" noreply\r\n",
+        ])
+        .await?;
+        self.conn.flush().await?;
+
+        Ok(())
+    }
+
+    /// Gets the given key through the meta protocol.
+    ///
+    /// Only the parts of the value requested through `flags` are returned: for example, the data
+    /// is only returned if [`GetFlags::want_value`] is set.  If the key is found, `Some(MetaValue)`
+    /// is returned, and `None` is returned if it was not found.
+    ///
+    /// Available as of memcached 1.6.0.
+    pub async fn meta_get<K: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        flags: &GetFlags,
+    ) -> Result<Option<MetaValue>, Error> {
+        let (key, flags) = flags.0.encode_key(key.as_ref());
+
+        let mut bf = Vec::new();
+        bf.extend(b"mg ");
+        bf.extend(key.as_ref());
+        flags.write_tokens(&mut bf);
+        bf.extend(b"\r\n");
+        self.conn.write_all(&bf).await?;
+        self.conn.flush().await?;
+
+        match self.get_meta_response().await? {
+            MetaResponse::Value(tokens, data) => {
+                Ok(Some(MetaValue::from_tokens(&tokens, Some(data))))
+            }
+            MetaResponse::Header(tokens) => Ok(Some(MetaValue::from_tokens(&tokens, None))),
+            MetaResponse::Status(Status::NotFound) => Ok(None),
+            MetaResponse::Status(s) => Err(s.into()),
+        }
+    }
+
+    /// Deletes the given key through the meta protocol.
+    ///
+    /// Returns `true` if the key was deleted, and `false` if it was not found.  With
+    /// [`DeleteFlags::compare_cas`], the key is only deleted if its CAS identifier matches, and
+    /// [`CommandError::Exists`] is returned otherwise.  With [`DeleteFlags::invalidate`],
+    /// the key is marked as stale rather than deleted, and [`DeleteFlags::set_ttl`] then sets its
+    /// new TTL; see [`Client::invalidate`].
+    ///
+    /// Available as of memcached 1.6.0.
+    pub async fn meta_delete<K: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        flags: &DeleteFlags,
+    ) -> Result<bool, Error> {
+        let (key, flags) = flags.0.encode_key(key.as_ref());
+
+        let mut bf = Vec::new();
+        bf.extend(b"md ");
+        bf.extend(key.as_ref());
+        flags.write_tokens(&mut bf);
+        bf.extend(b"\r\n");
+        self.conn.write_all(&bf).await?;
+        self.conn.flush().await?;
+
+        match self.get_meta_response().await? {
+            MetaResponse::Header(_) => Ok(true),
+            MetaResponse::Status(Status::NotFound) => Ok(false),
+            MetaResponse::Status(s) => Err(s.into()),
+            r => Err(Status::Error(r.unexpected("md")).into()),
+        }
+    }
+
+    /// Marks the given key as stale, and sets its TTL to `stale_ttl` seconds, rather than deleting
+    /// it.
+    ///
+    /// Returns `true` if the key was invalidated, and `false` if it was not found.  Until it
+    /// expires, the stale value is still returned by [`Client::meta_get`], and the first client
+    /// to get it wins the right to recompute it, as reported by [`MetaValue::recache`], while the
+    /// others can serve the stale value in the meantime.  This allows stale-while-revalidate
+    /// rather than every client missing at once after a delete.
+    ///
+    /// Available as of memcached 1.6.0.
+    pub async fn invalidate<K: AsRef<[u8]>>(
+        &mut self,
+        key: K,
+        stale_ttl: i64,
+    ) -> Result<bool, Error> {
+        self.meta_delete(key, &DeleteFlags::new().invalidate().set_ttl(stale_ttl))
+            .await
+    }
+
+    /// Applies `delta` to the given counter through the meta arithmetic command, with the
+    /// operation chosen by `mode`.
+    ///
+    /// If the key is found, `Some(CounterValue)` is returned with the value of the counter after
+    /// the operation, along with its CAS identifier and TTL if requested through `flags`.  `None`
+    /// is returned if the key was not found, unless [`ArithmeticFlags::autovivify`] is set, in
+    /// which case the counter is created with the value of [`ArithmeticFlags::initial_value`] (or
+    /// 0).
+    ///
+    /// As with [`Client::increment`] and [`Client::decrement`], decrementing a counter below 0
+    /// leaves it at 0 rather than wrapping, while incrementing it past the maximum 64-bit value
+    /// wraps around.  Unlike them, a missing key can be created in the same round trip, and the
+    /// TTL of the key can be updated with [`ArithmeticFlags::set_ttl`].
+    ///
+    /// If the key exists but the value is non-numeric, the server will return a ClientError.
+    ///
+    /// Available as of memcached 1.6.0.
+    pub async fn meta_arithmetic<K>(
+        &mut self,
+        key: K,
+        mode: ArithmeticMode,
+        delta: u64,
+        flags: &ArithmeticFlags,
+    ) -> Result<Option<CounterValue>, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let (key, flags) = flags.0.encode_key(key.as_ref());
+
+        let mut bf = Vec::new();
+        bf.extend(b"ma ");
+        bf.extend(key.as_ref());
+        flags.into_owned().want_value().write_tokens(&mut bf);
+        bf.extend(b" D");
+        bf.extend(delta.to_string().as_bytes());
+        bf.extend(b" ");
+        bf.extend(mode.token());
+        bf.extend(b"\r\n");
+        self.conn.write_all(&bf).await?;
+        self.conn.flush().await?;
+
+        match self.get_meta_response().await? {
+            MetaResponse::Status(Status::NotFound) => Ok(None),
+            MetaResponse::Status(s) => Err(s.into()),
+            MetaResponse::Value(tokens, data) => {
+                let value = btoi::btou(&data).map_err(|_| {
+                    Status::Error(ErrorKind::Protocol(Some(format!(
+                        "Invalid counter value for `ma` command: `{}`",
+                        String::from_utf8_lossy(&data)
+                    ))))
+                })?;
+                let meta = MetaValue::from_tokens(&tokens, None);
+                Ok(Some(CounterValue {
+                    value,
+                    cas: meta.cas,
+                    ttl: meta.ttl,
+                }))
+            }
+            r => Err(Status::Error(r.unexpected("ma")).into()),
+        }
+    }
+
+    /// Applies `delta` to the given counter, creating it with the value of `initial` if it does not
+    /// yet exist.
+    ///
+    /// Uses the meta arithmetic command, so the counter is created and updated in a single round
+    /// trip.  A negative `delta` decrements the counter, which will not go below 0.  The `ttl` is
+    /// only applied when the counter is created.  Returns the value of the counter after the
+    /// operation.
+    ///
+    /// If the key exists but the value is non-numeric, the server will return a ClientError.
+    ///
+    /// Available as of memcached 1.6.0.
+    pub async fn counter<K>(
+        &mut self,
+        key: K,
+        delta: i64,
+        initial: u64,
+        ttl: i64,
+    ) -> Result<u64, Error>
+    where
+        K: AsRef<[u8]>,
+    {
+        let mode = if delta < 0 {
+            ArithmeticMode::Decrement
+        } else {
+            ArithmeticMode::Increment
+        };
+        let flags = ArithmeticFlags::new()
+            .autovivify(ttl)
+            .initial_value(initial);
+
+        match self
+            .meta_arithmetic(key, mode, delta.unsigned_abs(), &flags)
+            .await?
+        {
+            Some(counter) => Ok(counter.value),
+            None => Err(Status::NotFound.into()),
+        }
+    }
+
+    /// Writes each of the given raw command lines and reads back one raw response line for each.
+    ///
+    /// Each line is terminated with CRLF, and all of them are written before flushing once, so the
+    /// commands are pipelined in a single round trip.  Responses are returned in the same order as
+    /// the commands, without their trailing CRLF.
+    ///
+    /// It is the caller's responsibility to only send commands which produce exactly one response
+    /// line: commands that reply with a data block, multiple lines, or nothing at all (`noreply`)
+    /// will leave the connection out of sync with the responses read.
+    pub async fn pipeline_raw(&mut self, lines: &[&[u8]]) -> Result<Vec<Vec<u8>>, Error> {
+        for line in lines {
+            self.write_all_vectored(&[line, b"\r\n"]).await?;
+        }
+        self.conn.flush().await?;
+
+        let mut responses = Vec::with_capacity(lines.len());
+        for _ in lines {
+            responses.push(self.drive_receive(parse_ascii_line).await?);
+        }
+
+        Ok(responses)
+    }
+
+    /// Gets the version of the server.
+    ///
+    /// If the version is retrieved successfully, `String` is returned containing the version
+    /// component e.g. `1.6.7`, otherwise [`Error`] is returned.
+    ///
+    /// For some setups, such as those using Twemproxy, this will return an error as those
+    /// intermediate proxies do not support the version command.
+    pub async fn version(&mut self) -> Result<String, Error> {
+        self.conn.write_all(b"version\r\n").await?;
+        self.conn.flush().await?;
+
+        let line = self.drive_receive(parse_ascii_line).await?;
+
+        // Peel off the leading "VERSION " header.
+        if let Some(version) = line.strip_prefix(b"VERSION ") {
+            return Ok(String::from_utf8_lossy(version).into_owned());
+        }
+
+        let kind = error_line_kind(&line).unwrap_or_else(|| {
+            ErrorKind::Protocol(Some(format!(
+                "Invalid response for `version` command: `{}`",
+                String::from_utf8_lossy(&line)
+            )))
+        });
+        Err(Status::Error(kind).into())
+    }
+
+    /// Waits until the server has processed every command previously sent on this connection.
+    ///
+    /// Commands sent with `noreply`, such as [`Client::delete_no_reply`] or
+    /// [`Client::increment_no_reply`], are not acknowledged, so there is otherwise no way to know
+    /// whether the server has received them.  As the server processes commands in order, sending
+    /// a `version` command after a burst of them and waiting for its response confirms that all of
+    /// them have been processed.  This is the standard way to flush and confirm a `noreply`
+    /// pipeline without paying for an acknowledgement per command.
+    ///
+    /// Some errors, such as a malformed data block, are still reported for `noreply` commands.  If
+    /// any such error is read before the `version` response, the first one is returned once the
+    /// connection has been synchronized.
+    pub async fn sync(&mut self) -> Result<(), Error> {
+        self.conn.write_all(b"version\r\n").await?;
+        self.conn.flush().await?;
+
+        let mut first_err = None;
+        loop {
+            let line = self.drive_receive(parse_ascii_line).await?;
+            if line.starts_with(b"VERSION ") {
+                break;
+            }
+
+            let kind = error_line_kind(&line).unwrap_or_else(|| {
+                ErrorKind::Protocol(Some(format!(
+                    "unexpected response before `version`: `{}`",
+                    String::from_utf8_lossy(&line)
+                )))
+            });
+            first_err.get_or_insert(Status::Error(kind));
+        }
+
+        match first_err {
+            Some(s) => Err(s.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that values can be stored on and retrieved from the server.
+    ///
+    /// A unique temporary key is set, read back, compared against the value which was set, and
+    /// then deleted.  Unlike [`Client::version`], which only shows that the server is reachable,
+    /// this catches read-only servers and proxies which silently drop writes, so it is suited to
+    /// readiness probes.  The key expires after a minute, in case it could not be deleted.
+    ///
+    /// If any step fails, its error is returned, and [`ErrorKind::Generic`] is returned if the
+    /// key was missing or had the wrong value when read back.
+    pub async fn self_test(&mut self) -> Result<(), Error> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let key = format!(
+            "async-memcached-self-test-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            SELF_TEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+
+        self.set(&key, key.as_str(), Some(SELF_TEST_TTL), Some(0))
+            .await?;
+        let result = match self.get(&key).await? {
+            Some(value) if value.data == key.as_bytes() => Ok(()),
+            Some(value) => Err(format!(
+                "self test key `{}` was read back as {} bytes which do not match the {} bytes set",
+                key,
+                value.data.len(),
+                key.len()
+            )),
+            None => Err(format!(
+                "self test key `{}` was not found after being set",
+                key
+            )),
+        };
+        self.delete(&key).await?;
+
+        result.map_err(|msg| Status::Error(ErrorKind::Generic(msg)).into())
+    }
+
+    /// Measures the round trip time to the server.
+    ///
+    /// This times a `version` command, which the server answers without doing any other work, so
+    /// the result reflects network latency and how busy the server is.  It is useful as a health
+    /// check, or to surface connection quality to operators.
+    ///
+    /// As this is built on [`Client::sync`], any error reported for an earlier `noreply` command is
+    /// returned instead of the measurement.
+    pub async fn measure_rtt(&mut self) -> Result<Duration, Error> {
+        let start = Instant::now();
+        self.sync().await?;
+        Ok(start.elapsed())
+    }
+
+    /// Dumps all keys from the server.
+    ///
+    /// This operation scans all slab classes from tail to head, in a non-blocking fashion.  Thus,
+    /// not all items will be found as new items could be inserted or deleted while the crawler is
+    /// still running.
+    ///
+    /// [`MetadumpIter`] must be iterated over to discover whether or not the crawler successfully
+    /// started, as this call will only return [`Error`] if the command failed to be written to the
+    /// server at all.
+    ///
+    /// Available as of memcached 1.4.31.
+    pub async fn dump_keys(&mut self) -> Result<MetadumpIter<'_>, Error> {
+        self.conn.write_all(b"lru_crawler metadump all\r\n").await?;
+        self.conn.flush().await?;
+
+        Ok(MetadumpIter {
+            client: self,
+            done: false,
+            pending: None,
+            timeout: None,
+            timed_out: false,
+        })
+    }
+
+    /// Dumps all keys from the server, bounding how long each step of the dump may take.
+    ///
+    /// Behaves like [`Client::dump_keys`], except that if the server sends nothing for `timeout`
+    /// while [`MetadumpIter::next`] waits for the next key, `Some(Err(Error::Timeout { .. }))`
+    /// is returned, with an operation of `metadump`.  No data is lost when this happens, so `next`
+    /// can be called again to keep waiting for the dump to continue.  As the rest of the dump is
+    /// still on its way, the client is poisoned until then, so if the iterator is dropped instead,
+    /// the client must be discarded, or recovered with [`Client::drain`] once the dump has ended.
+    ///
+    /// Available as of memcached 1.4.31.
+    pub async fn dump_keys_with_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<MetadumpIter<'_>, Error> {
+        let mut iter = self.dump_keys().await?;
+        iter.timeout = Some(timeout);
+        Ok(iter)
+    }
+
+    /// Dumps all keys from the server, waiting for the LRU crawler to become available if it is
+    /// busy.
+    ///
+    /// Behaves like [`Client::dump_keys`], except that if the crawler is busy with another
+    /// operation, the metadump is retried periodically until it starts or `max_wait` has elapsed.
+    /// If the crawler is still busy after `max_wait`, the busy error is returned.  An invalid
+    /// class is not retried and is still yielded by [`MetadumpIter`].
+    ///
+    /// Available as of memcached 1.4.31.
+    pub async fn dump_keys_retry(&mut self, max_wait: Duration) -> Result<MetadumpIter<'_>, Error> {
+        let start = Instant::now();
+        let first = loop {
+            self.conn.write_all(b"lru_crawler metadump all\r\n").await?;
+            self.conn.flush().await?;
+
+            match self.get_metadump_response().await? {
+                MetadumpResponse::Busy(s) => {
+                    let elapsed = start.elapsed();
+                    if elapsed >= max_wait {
+                        return Err(Status::from(MetadumpResponse::Busy(s)).into());
+                    }
+                    tokio::time::sleep(METADUMP_RETRY_INTERVAL.min(max_wait - elapsed)).await;
+                }
+                response => break response,
+            }
+        };
+
+        Ok(MetadumpIter {
+            client: self,
+            done: false,
+            pending: Some(first),
+            timeout: None,
+            timed_out: false,
+        })
+    }
+
+    /// Deletes every key that starts with the given prefix, returning the number of keys deleted.
+    ///
+    /// Keys are enumerated with [`Client::dump_keys`] and then deleted through pipelined commands,
+    /// written in windows of 100 whose responses are read before the next window is written.  If
+    /// the server returns an error, no further windows are written.  If writing a delete or reading
+    /// a response fails, the client is poisoned.
+    /// This is not atomic: keys matching the prefix that are added while the crawler is running,
+    /// or after it has passed them, will not be deleted.  As every key on the server is scanned,
+    /// this is an expensive operation and should not be used on a hot path.
+    ///
+    /// Available as of memcached 1.4.31.
+    pub async fn delete_prefix(&mut self, prefix: &[u8]) -> Result<usize, Error> {
+        let mut keys = Vec::new();
+        let mut iter = self.dump_keys().await?;
+        while let Some(result) = iter.next().await {
+            let key = decode_metadump_key(&result?.key);
+            if key.starts_with(prefix) {
+                keys.push(key);
+            }
+        }
+
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let mut deleted = 0;
+        let mut first_err = None;
+        if let Err(e) = self
+            .pipeline_deletes(&keys, &mut deleted, &mut first_err)
+            .await
+        {
+            // Responses to deletes which were written but not read are still on their way.
+            self.poisoned = true;
+            return Err(e);
+        }
+
+        match first_err {
+            Some(s) => Err(s.into()),
+            None => Ok(deleted),
+        }
+    }
+
+    /// Writes a `delete` for each key, in windows of [`PIPELINED_DELETE_WINDOW`], reading the
+    /// responses for each window before writing the next.
+    ///
+    /// Writing every delete before reading any response could fill both sides' socket buffers and
+    /// deadlock.  Once the server returns an error, it is kept in `first_err`, and no further
+    /// windows are written, but every response for the current window is still read.
+    async fn pipeline_deletes(
+        &mut self,
+        keys: &[Vec<u8>],
+        deleted: &mut usize,
+        first_err: &mut Option<Status>,
+    ) -> Result<(), Error> {
+        for window in keys.chunks(PIPELINED_DELETE_WINDOW) {
+            for key in window {
+                self.write_all_vectored(&[b"delete ", key, b"\r\n"]).await?;
+            }
+            self.conn.flush().await?;
+
+            for _ in window {
+                match self.get_read_write_response().await? {
+                    Response::Status(Status::Deleted) => *deleted += 1,
+                    Response::Status(Status::NotFound) => {}
+                    Response::Status(s) => {
+                        first_err.get_or_insert(s);
+                    }
+                    r => {
+                        first_err.get_or_insert(Status::Error(r.unexpected("delete")));
+                    }
+                }
+            }
+
+            if first_err.is_some() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// Collects statistics from the server.
+    ///
+    /// The statistics that may be returned are detailed in the protocol specification for
+    /// memcached, but all values returned by this method are returned as strings and are not
+    /// further interpreted or validated for conformity.
+    pub async fn stats(&mut self) -> Result<FxHashMap<String, String>, Error> {
+        self.collect_stats(None).await
+    }
+
+    /// Streams statistics from the server, optionally for the given subcommand, e.g. `settings` or
+    /// `slabs`.
+    ///
+    /// Rather than collecting every entry like [`Client::stats`], entries are read from the
+    /// connection one at a time as [`StatsIter`] is iterated over, so large outputs never need to
+    /// be held in memory at once.  As with [`Client::dump_keys`], [`StatsIter`] must be iterated
+    /// over until it returns `None` to consume the whole response before the client is reused.
+    pub async fn stats_stream(&mut self, subcommand: Option<&str>) -> Result<StatsIter<'_>, Error> {
+        let mut bf = Vec::new();
+        bf.extend(b"stats");
+        if let Some(subcommand) = subcommand {
+            bf.extend(b" ");
+            bf.extend(subcommand.as_bytes());
+        }
+        bf.extend(b"\r\n");
+        self.conn.write_all(&bf).await?;
+        self.conn.flush().await?;
+
+        Ok(StatsIter {
+            client: self,
+            done: false,
+        })
+    }
+
+    /// Collects the entries of the given stats subcommand.
+    async fn collect_stats(
+        &mut self,
+        subcommand: Option<&str>,
+    ) -> Result<FxHashMap<String, String>, Error> {
+        let mut entries = FxHashMap::default();
+
+        let mut iter = self.stats_stream(subcommand).await?;
+        while let Some(entry) = iter.next().await {
+            let (key, value) = entry?;
+            entries.insert(key, value);
+        }
+
+        Ok(entries)
+    }
+
+    /// Collects general statistics, settings and item statistics from the server together.
+    ///
+    /// This issues `stats`, `stats settings` and `stats items` one after another, reading each
+    /// response through to its end marker before the next command is written, which is useful
+    /// for dashboards that show all three.
+    pub async fn stats_bundle(&mut self) -> Result<StatsBundle, Error> {
+        Ok(StatsBundle {
+            general: self.collect_stats(None).await?,
+            settings: self.collect_stats(Some("settings")).await?,
+            items: self.collect_stats(Some("items")).await?,
+        })
+    }
+
+    /// Collects the most commonly used counters from the server's statistics.
+    ///
+    /// This is computed from the same output as [`Client::stats`], which should be used for any
+    /// statistics not included in [`StatsSummary`].
+    pub async fn stats_summary(&mut self) -> Result<StatsSummary, Error> {
+        let stats = self.stats().await?;
+        Ok(StatsSummary::from_stats(&stats))
+    }
+
+    /// Gets the number of items currently stored, from `curr_items` in the server's statistics.
+    ///
+    /// This is much cheaper than counting keys with [`Client::dump_keys`], but includes items
+    /// which have expired and not yet been reclaimed.
+    pub async fn item_count(&mut self) -> Result<u64, Error> {
+        self.stats_counter("curr_items").await
+    }
+
+    /// Gets the total number of items stored since the server started, from `total_items` in the
+    /// server's statistics.
+    pub async fn total_items(&mut self) -> Result<u64, Error> {
+        self.stats_counter("total_items").await
+    }
+
+    /// Gets a single counter from the output of `stats`.
+    ///
+    /// Unlike [`Client::stats_summary`], a counter which is missing or cannot be parsed is an
+    /// error rather than 0.
+    async fn stats_counter(&mut self, name: &str) -> Result<u64, Error> {
+        let stats = self.stats().await?;
+        stats
+            .get(name)
+            .and_then(|v| v.parse::<u64>().ok())
+            .ok_or_else(|| {
+                Status::Error(ErrorKind::Protocol(Some(format!(
+                    "missing or invalid stat `{}`",
+                    name
+                ))))
+                .into()
+            })
+    }
+
+    /// Gets the thresholds enforced by the server when storing items.
+    ///
+    /// The maximum value size is read from `stats settings` the first time this is called, and
+    /// cached for the lifetime of the client.  If the server does not report it, memcached's
+    /// default of 1MB is assumed.
+    pub async fn limits(&mut self) -> Result<Limits, Error> {
+        if let Some(limits) = &self.limits {
+            return Ok(limits.clone());
+        }
+
+        let settings = self.collect_stats(Some("settings")).await?;
+        let limits = Limits::from_settings(&settings);
+        self.limits = Some(limits.clone());
+        Ok(limits)
+    }
+
+    /// Gets the current time according to the server's clock.
+    ///
+    /// The time is read from the `time` field of `stats`, which has a resolution of one second.  To
+    /// avoid a `stats` round trip on every call, the result is cached and advanced by the local
+    /// monotonic clock for a short period before the server is queried again.
+    pub async fn server_time(&mut self) -> Result<SystemTime, Error> {
+        if let Some((fetched_at, time)) = self.server_time {
+            let elapsed = fetched_at.elapsed();
+            if elapsed < SERVER_TIME_CACHE_TTL {
+                return Ok(time + elapsed);
+            }
+        }
+
+        let stats = self.stats().await?;
+        let secs = stats
+            .get("time")
+            .and_then(|t| t.parse::<u64>().ok())
+            .ok_or_else(|| {
+                Error::from(Status::Error(ErrorKind::Protocol(Some(
+                    "Missing or invalid `time` field in `stats` response".to_string(),
+                ))))
+            })?;
+
+        let time = UNIX_EPOCH + Duration::from_secs(secs);
+        self.server_time = Some((Instant::now(), time));
+        Ok(time)
+    }
+
+    /// Flushes all existing items on the server
+    ///
+    /// This operation invalidates all existing items immediately. Any items with an update time
+    /// older than the time of the flush_all operation will be ignored for retrieval purposes.
+    /// This operation does not free up memory taken up by the existing items.
+    pub async fn flush_all(&mut self) -> Result<(), Error> {
+        self.write_flush_all(None, false).await?;
+        self.read_flush_all_response().await
+    }
+
+    /// Flushes all existing items on the server after `delay` seconds.
+    ///
+    /// Items are invalidated as by [`Client::flush_all`], but only once the delay has passed, so
+    /// that the flush can be scheduled, or staggered across several servers to avoid all of them
+    /// missing at once.
+    pub async fn flush_all_delayed(&mut self, delay: u32) -> Result<(), Error> {
+        self.write_flush_all(Some(delay), false).await?;
+        self.read_flush_all_response().await
+    }
+
+    /// Flushes all existing items on the server, optionally after `delay` seconds, but doesn't
+    /// wait for a reply.
+    pub async fn flush_all_no_reply(&mut self, delay: Option<u32>) -> Result<(), Error> {
+        self.write_flush_all(delay, true).await
+    }
+
+    async fn write_flush_all(&mut self, delay: Option<u32>, no_reply: bool) -> Result<(), Error> {
+        let mut bf = Vec::new();
+        bf.extend(b"flush_all");
+        if let Some(delay) = delay {
+            bf.extend(b" ");
+            bf.extend(delay.to_string().as_bytes());
+        }
+        if no_reply {
+            bf.extend(b" noreply");
+        }
+        bf.extend(b"\r\n");
+        self.conn.write_all(&bf).await?;
+        self.conn.flush().await?;
+        Ok(())
+    }
+
+    async fn read_flush_all_response(&mut self) -> Result<(), Error> {
+        let line = self.drive_receive(parse_ascii_line).await?;
+        if line == b"OK" {
+            return Ok(());
+        }
+
+        Err(match error_line_kind(&line) {
+            Some(kind) => Status::Error(kind).into(),
+            None => Status::Error(ErrorKind::Protocol(Some(format!(
+                "Invalid response for `flush_all` command: `{}`",
+                String::from_utf8_lossy(&line)
+            ))))
+            .into(),
+        })
+    }
+}
+
+/// Decodes a key as returned by a metadump, which percent-encodes any reserved characters.
+fn decode_metadump_key(key: &[u8]) -> Vec<u8> {
+    let hex = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+
+    let mut decoded = Vec::with_capacity(key.len());
+    let mut i = 0;
+    while i < key.len() {
+        match (
+            key[i],
+            key.get(i + 1).copied().and_then(hex),
+            key.get(i + 2).copied().and_then(hex),
+        ) {
+            (b'%', Some(hi), Some(lo)) => {
+                decoded.push(hi << 4 | lo);
+                i += 3;
+            }
+            (c, _, _) => {
+                decoded.push(c);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Whether a client error means the server rejected a data block, after which the rest of the block
+/// is read by the server as further commands.
+fn is_desync_error(msg: &str) -> bool {
+    msg == "bad data chunk"
+}
+
+/// Whether `line` follows the grammar of a storage command line, i.e.
+/// `<command> <key> <flags> <ttl> <bytes>[ noreply]\r\n`.
+///
+/// The key is not validated, as it is given by the caller, so this only catches mistakes in how
+/// the rest of the line is assembled.
+fn is_storage_command_line(line: &[u8]) -> bool {
+    let line = match line.strip_suffix(b"\r\n") {
+        Some(line) => line,
+        None => return false,
+    };
+    let line = line.strip_suffix(b" noreply").unwrap_or(line);
+
+    let mut fields = line.rsplitn(4, |b| *b == b' ');
+    let numeric = |field: Option<&[u8]>, signed: bool| match field {
+        Some([b'-', rest @ ..]) if signed => {
+            !rest.is_empty() && rest.iter().all(u8::is_ascii_digit)
+        }
+        Some(field) => !field.is_empty() && field.iter().all(u8::is_ascii_digit),
+        None => false,
+    };
+    if !numeric(fields.next(), false)
+        || !numeric(fields.next(), true)
+        || !numeric(fields.next(), false)
+    {
+        return false;
+    }
+
+    // What remains is the command and the key, which must both be present.
+    let rest = fields.next().unwrap_or_default();
+    match rest.iter().position(|b| *b == b' ') {
+        Some(i) => i > 0 && i + 1 < rest.len() && rest[..i].iter().all(u8::is_ascii_lowercase),
+        None => false,
+    }
+}
+
+/// Message of the protocol error returned when a response line exceeds the configured maximum.
+const LINE_TOO_LONG: &str = "line too long";
+
+fn is_line_too_long(e: &Error) -> bool {
+    matches!(e, Error::Protocol(ErrorKind::Protocol(Some(msg))) if msg == LINE_TOO_LONG)
+}
+
+/// Whether a `SERVER_ERROR` message means a value was too large to store.
+///
+/// Running out of memory is not the same failure, so `out of memory storing object` is left as
+/// [`ErrorKind::Server`].
+fn is_too_large_error(msg: &str) -> bool {
+    msg == "object too large for cache"
+}
+
+/// Classifies a response line as an error, if it is one.
+fn error_line_kind(line: &[u8]) -> Option<ErrorKind> {
+    match line {
+        b"ERROR" => Some(ErrorKind::NonexistentCommand),
+        l if l.starts_with(b"CLIENT_ERROR ") => Some(ErrorKind::from_client_error(
+            &String::from_utf8_lossy(&l[13..]),
+        )),
+        l if l.starts_with(b"SERVER_ERROR ") => Some(ErrorKind::Server(
+            String::from_utf8_lossy(&l[13..]).into_owned(),
+        )),
+        _ => None,
+    }
+}
+
+/// Asynchronous iterator for metadump operations.
+pub struct MetadumpIter<'a> {
+    client: &'a mut Client,
+    done: bool,
+    pending: Option<MetadumpResponse>,
+    timeout: Option<Duration>,
+    timed_out: bool,
+}
+
+impl<'a> MetadumpIter<'a> {
+    /// Gets the next result for the current operation.
+    ///
+    /// If there is another key in the dump, `Some(Ok(KeyMetadata))` will be returned.  If there was
+    /// an error while attempting to start the metadump operation, or if there was a general
+    /// network/protocol-level error, `Some(Err(Error))` will be returned.
+    ///
+    /// Otherwise, `None` will be returned and signals the end of the iterator.  Subsequent calls
+    /// will return `None`.
+    pub async fn next(&mut self) -> Option<Result<KeyMetadata, Error>> {
+        if self.done {
+            return None;
+        }
+
+        // The client is left poisoned by a timeout, as the rest of the dump is still on its way,
+        // until the dump is resumed here.
+        if std::mem::take(&mut self.timed_out) {
+            self.client.poisoned = false;
+        }
+
+        let response = match (self.pending.take(), self.timeout) {
+            (Some(response), _) => Ok(response),
+            (None, Some(timeout)) => {
+                match tokio::time::timeout(timeout, self.client.get_metadump_response()).await {
+                    Ok(response) => response,
+                    Err(_) => {
+                        // Reading a response only buffers data which has been received, so the
+                        // dump can be resumed by the next call despite the read being cancelled.
+                        self.timed_out = true;
+                        return Some(Err(self.client.timeout_error("metadump", timeout)));
+                    }
+                }
+            }
+            (None, None) => self.client.get_metadump_response().await,
+        };
+
+        match response {
+            Ok(MetadumpResponse::End) => {
+                self.done = true;
+                None
+            }
+            Ok(MetadumpResponse::BadClass(s)) => {
+                self.done = true;
+                Some(Err(Status::from(MetadumpResponse::BadClass(s)).into()))
+            }
+            Ok(MetadumpResponse::Busy(s)) => {
+                Some(Err(Status::from(MetadumpResponse::Busy(s)).into()))
+            }
+            Ok(MetadumpResponse::Entry(km)) => Some(Ok(km)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Asynchronous iterator for stats operations.
+pub struct StatsIter<'a> {
+    client: &'a mut Client,
+    done: bool,
+}
+
+impl<'a> StatsIter<'a> {
+    /// Gets the next entry for the current operation.
+    ///
+    /// If there is another entry in the output, `Some(Ok((key, value)))` will be returned.  If the
+    /// server rejected the stats command, or if there was a general network/protocol-level error,
+    /// `Some(Err(Error))` will be returned, and the iterator ends.
+    ///
+    /// Otherwise, `None` will be returned and signals the end of the iterator.  Subsequent calls
+    /// will return `None`.
+    pub async fn next(&mut self) -> Option<Result<(String, String), Error>> {
+        if self.done {
+            return None;
+        }
+
+        match self.client.get_stats_response().await {
+            Ok(StatsResponse::Entry(key, value)) => Some(Ok((key, value))),
+            Ok(StatsResponse::End) | Ok(StatsResponse::Done) => {
+                self.done = true;
                 None
             }
-            Ok(MetadumpResponse::BadClass(s)) => {
-                self.done = true;
-                Some(Err(Error::Protocol(MetadumpResponse::BadClass(s).into())))
+            Ok(StatsResponse::Error(kind)) => {
+                self.done = true;
+                Some(Err(Status::Error(kind).into()))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_metadump_key, is_storage_command_line, AsyncStream, Client, ClientOptions,
+        CommandError, Connection, ConnectionInfo, DeleteFlags, Error, ErrorKind, FxHashMap,
+        Response, Value, WithContext,
+    };
+    use proptest::prelude::*;
+    use std::collections::HashMap;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use tokio::io::{
+        AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter,
+        ReadBuf,
+    };
+    use tokio::net::UnixStream;
+
+    fn client_with_server() -> (Client, UnixStream) {
+        let (client, server) = UnixStream::pair().expect("failed to create socket pair");
+        let conn = Connection::Unix(BufReader::new(BufWriter::new(client)));
+        (Client::from_connection(conn), server)
+    }
+
+    #[test]
+    fn test_decode_metadump_key() {
+        assert_eq!(decode_metadump_key(b"foo"), b"foo".to_vec());
+        assert_eq!(decode_metadump_key(b"foo%3Abar"), b"foo:bar".to_vec());
+        assert_eq!(decode_metadump_key(b"100%25"), b"100%".to_vec());
+        assert_eq!(decode_metadump_key(b"bad%zz%"), b"bad%zz%".to_vec());
+    }
+
+    #[test]
+    fn test_key_str() {
+        let mut value = Value {
+            key: b"foo".to_vec(),
+            cas: None,
+            flags: 0,
+            data: Vec::new(),
+            ttl: None,
+        };
+        assert_eq!(value.key_str(), Ok("foo"));
+
+        value.key = b"caf\xc3\xa9\xff".to_vec();
+        assert!(value.key_str().is_err());
+    }
+
+    #[test]
+    fn test_unexpected_response_is_described() {
+        assert_eq!(
+            Response::IncrDecr(1).unexpected("get"),
+            ErrorKind::Protocol(Some("unexpected IncrDecr response to get".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_read_poisons_client() {
+        let (mut client, mut server) = client_with_server();
+
+        // The server never answers in time, so the read is cancelled part way through.
+        let result = tokio::time::timeout(Duration::from_millis(10), client.get("foo")).await;
+        assert!(result.is_err());
+        assert!(client.is_poisoned());
+
+        // The late response to the first command must not be returned for the next one.
+        server
+            .write_all(b"VALUE foo 0 3\r\nbar\r\nEND\r\n")
+            .await
+            .unwrap();
+        let result = client.get("baz").await;
+        assert!(
+            matches!(result, Err(Error::Transport(_))),
+            "got {:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stats_leaves_trailing_response() {
+        let (mut client, mut server) = client_with_server();
+
+        // Both responses arrive in a single read, so the stats loop must stop at its own `END`.
+        server
+            .write_all(b"STAT pid 1\r\nSTAT uptime 2\r\nEND\r\nVALUE foo 0 3\r\nbar\r\nEND\r\n")
+            .await
+            .unwrap();
+
+        let stats = client.stats().await.expect("failed to get stats");
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats.get("uptime").map(String::as_str), Some("2"));
+
+        let value = client
+            .get("foo")
+            .await
+            .expect("failed to get")
+            .expect("value should be found");
+        assert_eq!(value.key, b"foo".to_vec());
+        assert_eq!(value.data, b"bar".to_vec());
+        assert!(!client.has_pending_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_has_pending_bytes() {
+        let (mut client, mut server) = client_with_server();
+        assert!(!client.has_pending_bytes());
+
+        server.write_all(b"END\r\n").await.unwrap();
+        assert_eq!(client.get("foo").await.expect("failed to get"), None);
+        assert!(!client.has_pending_bytes());
+
+        server.write_all(b"END\r\nVALUE bar 0 3\r\n").await.unwrap();
+        assert_eq!(client.get("foo").await.expect("failed to get"), None);
+        assert!(client.has_pending_bytes());
+    }
+
+    #[tokio::test]
+    #[allow(deprecated)]
+    async fn test_multi_operations_with_no_keys() {
+        let (mut client, mut server) = client_with_server();
+        let no_keys: [&str; 0] = [];
+        let no_kv: [(&str, &str); 0] = [];
+
+        assert_eq!(client.get_multi(no_keys).await.unwrap(), Vec::new());
+        assert_eq!(client.get_many(no_keys).await.unwrap(), Vec::new());
+        assert_eq!(
+            client.get_multi_pipelined(no_keys, 4).await.unwrap(),
+            Vec::new()
+        );
+        assert!(client
+            .set_multi(&no_kv, None, None)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(client
+            .add_multi(&no_kv, None, None)
+            .await
+            .unwrap()
+            .is_empty());
+        client.delete_multi_no_reply(&no_keys).await.unwrap();
+
+        // Nothing should have been written before the client was dropped.
+        drop(client);
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert!(written.is_empty(), "wrote {:?}", written);
+    }
+
+    #[tokio::test]
+    async fn test_sync_reports_noreply_errors() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"VERSION 1.6.21\r\n").await.unwrap();
+        assert_eq!(client.sync().await, Ok(()));
+
+        server
+            .write_all(b"CLIENT_ERROR bad data chunk\r\nERROR\r\nVERSION 1.6.21\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.sync().await,
+            Err(Error::Command(CommandError::Error(ErrorKind::Client(
+                "bad data chunk".to_string()
+            ))))
+        );
+        assert!(!client.has_pending_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_read_chunk_size_adapts_to_responses() {
+        let (mut client, mut server) = client_with_server();
+        assert_eq!(client.read_chunk_size(), 1024);
+
+        let data = vec![b'a'; 100 * 1024];
+        let mut response = b"VALUE foo 0 102400\r\n".to_vec();
+        response.extend_from_slice(&data);
+        response.extend_from_slice(b"\r\nEND\r\n");
+
+        let server_task = tokio::spawn(async move {
+            for _ in 0..4 {
+                server.write_all(&response).await.unwrap();
+            }
+            server
+        });
+        for _ in 0..4 {
+            let value = client.get("foo").await.unwrap().unwrap();
+            assert_eq!(value.data.len(), data.len());
+        }
+        assert_eq!(client.read_chunk_size(), 128 * 1024);
+
+        let mut server = server_task.await.unwrap();
+        for _ in 0..64 {
+            server.write_all(b"END\r\n").await.unwrap();
+            assert_eq!(client.get("foo").await.unwrap(), None);
+        }
+        assert_eq!(client.read_chunk_size(), 1024);
+    }
+
+    #[tokio::test]
+    async fn test_set_writes_large_value() {
+        let (mut client, mut server) = client_with_server();
+        let value = vec![b'a'; 512 * 1024];
+
+        let mut expected = b"set foo 0 0 524288\r\n".to_vec();
+        expected.extend_from_slice(&value);
+        expected.extend_from_slice(b"\r\n");
+
+        let server_task = tokio::spawn(async move {
+            let mut written = vec![0u8; expected.len()];
+            server.read_exact(&mut written).await.unwrap();
+            assert!(written == expected, "unexpected command written");
+            server.write_all(b"STORED\r\n").await.unwrap();
+        });
+
+        client
+            .set("foo", value.as_slice(), None, None)
+            .await
+            .unwrap();
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stats_stream() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"STAT slab_classes 2\r\nSTAT active_slabs 1\r\nEND\r\nERROR\r\n")
+            .await
+            .unwrap();
+
+        let mut iter = client.stats_stream(Some("slabs")).await.unwrap();
+        assert_eq!(
+            iter.next().await,
+            Some(Ok(("slab_classes".to_string(), "2".to_string())))
+        );
+        assert_eq!(
+            iter.next().await,
+            Some(Ok(("active_slabs".to_string(), "1".to_string())))
+        );
+        assert_eq!(iter.next().await, None);
+        assert_eq!(iter.next().await, None);
+
+        let mut iter = client.stats_stream(Some("bogus")).await.unwrap();
+        assert_eq!(
+            iter.next().await,
+            Some(Err(Error::Command(CommandError::Error(
+                ErrorKind::NonexistentCommand
+            ))))
+        );
+        assert_eq!(iter.next().await, None);
+
+        drop(client);
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert_eq!(written, b"stats slabs\r\nstats bogus\r\n".to_vec());
+    }
+
+    /// Counts the bytes written through the wrapped stream.
+    struct CountingStream {
+        inner: Box<dyn AsyncStream>,
+        written: Arc<AtomicUsize>,
+    }
+
+    impl AsyncRead for CountingStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for CountingStream {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+            if let Poll::Ready(Ok(n)) = result {
+                self.written.fetch_add(n, Ordering::SeqCst);
+            }
+            result
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.inner).poll_shutdown(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wrap_stream() {
+        let (client, mut server) = UnixStream::pair().expect("failed to create socket pair");
+        let mut client = Client::from_stream(client);
+
+        // A response which arrives before the stream is wrapped must still be read afterwards.
+        server
+            .write_all(b"END\r\nVALUE foo 0 3\r\nbar\r\nEND\r\n")
+            .await
+            .unwrap();
+        assert_eq!(client.get("foo").await.unwrap(), None);
+
+        let written = Arc::new(AtomicUsize::new(0));
+        let counter = written.clone();
+        let mut client = client
+            .wrap_stream(move |inner| CountingStream {
+                inner,
+                written: counter,
+            })
+            .await
+            .unwrap();
+
+        let value = client.get("foo").await.unwrap().unwrap();
+        assert_eq!(value.data, b"bar".to_vec());
+        assert_eq!(written.load(Ordering::SeqCst), b"get foo\r\n".len());
+    }
+
+    /// Serves `set` and `get` commands from an in-memory map, until the client disconnects.
+    async fn run_mock_server(server: UnixStream) {
+        let mut server = BufReader::new(server);
+        let mut items: HashMap<Vec<u8>, (u32, Vec<u8>)> = HashMap::new();
+
+        loop {
+            let mut line = Vec::new();
+            if server.read_until(b'\n', &mut line).await.unwrap() == 0 {
+                return;
+            }
+            line.truncate(line.len() - 2);
+            let parts: Vec<&[u8]> = line.split(|b| *b == b' ').collect();
+            let parse = |part: &[u8]| std::str::from_utf8(part).unwrap().parse::<usize>().unwrap();
+
+            match parts[0] {
+                b"set" => {
+                    let mut data = vec![0; parse(parts[4]) + 2];
+                    server.read_exact(&mut data).await.unwrap();
+                    data.truncate(data.len() - 2);
+                    items.insert(parts[1].to_vec(), (parse(parts[2]) as u32, data));
+                    if parts.last() != Some(&&b"noreply"[..]) {
+                        server.write_all(b"STORED\r\n").await.unwrap();
+                    }
+                }
+                b"delete" => match items.remove(parts[1]) {
+                    Some(_) => server.write_all(b"DELETED\r\n").await.unwrap(),
+                    None => server.write_all(b"NOT_FOUND\r\n").await.unwrap(),
+                },
+                b"get" => {
+                    let mut response = Vec::new();
+                    for key in &parts[1..] {
+                        if let Some((flags, data)) = items.get(*key) {
+                            response.extend_from_slice(b"VALUE ");
+                            response.extend_from_slice(key);
+                            response.extend_from_slice(
+                                format!(" {} {}\r\n", flags, data.len()).as_bytes(),
+                            );
+                            response.extend_from_slice(data);
+                            response.extend_from_slice(b"\r\n");
+                        }
+                    }
+                    response.extend_from_slice(b"END\r\n");
+                    server.write_all(&response).await.unwrap();
+                }
+                _ => server.write_all(b"ERROR\r\n").await.unwrap(),
             }
-            Ok(MetadumpResponse::Busy(s)) => {
-                Some(Err(Error::Protocol(MetadumpResponse::Busy(s).into())))
+            server.flush().await.unwrap();
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_set_get_round_trip(
+            key in prop::collection::vec(33u8..=255, 1..=250),
+            value in prop::collection::vec(any::<u8>(), 0..4096),
+            flags in any::<u32>(),
+        ) {
+            // Values containing framing bytes must come back byte-for-byte.
+            let framed = [&value[..], b"\r\nEND\r\n\0\xff"].concat();
+            // DEL is a control character, so it is not valid in a key.
+            let key = key
+                .into_iter()
+                .map(|b| if b == 127 { b'~' } else { b })
+                .collect::<Vec<u8>>();
+
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let (got, got_framed) = runtime.block_on(async {
+                let (mut client, server) = client_with_server();
+                let server_task = tokio::spawn(run_mock_server(server));
+
+                client.set(&key, value.as_slice(), None, Some(flags)).await.unwrap();
+                let got = client.get(&key).await.unwrap();
+                client.set(&key, framed.as_slice(), None, Some(flags)).await.unwrap();
+                let got_framed = client.get_multi([&key]).await.unwrap();
+
+                drop(client);
+                server_task.await.unwrap();
+                (got, got_framed)
+            });
+
+            let expected = Value { key: key.clone(), cas: None, flags, data: value, ttl: None };
+            prop_assert_eq!(got, Some(expected));
+            let expected = Value { key, cas: None, flags, data: framed, ttl: None };
+            prop_assert_eq!(got_framed, vec![expected]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_measure_rtt() {
+        let (mut client, mut server) = client_with_server();
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let n = server.read(&mut buf).await.unwrap();
+            assert_eq!(&buf[..n], b"version\r\n");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            server.write_all(b"VERSION 1.6.21\r\n").await.unwrap();
+            server
+        });
+
+        let rtt = client.measure_rtt().await.unwrap();
+        assert!(rtt >= Duration::from_millis(20), "rtt was {:?}", rtt);
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bad_data_chunk_poisons_client() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"CLIENT_ERROR bad data chunk\r\nERROR\r\n")
+            .await
+            .unwrap();
+        let result = client.set("foo", "bar", None, None).await;
+        assert_eq!(
+            result,
+            Err(Error::ProtocolDesync("bad data chunk".to_string()))
+        );
+        assert!(client.is_poisoned());
+
+        // The server's response to the rest of the data block must not be read as a response.
+        let result = client.get("foo").await;
+        assert!(
+            matches!(result, Err(Error::Transport(_))),
+            "got {:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_underwritten_value_poisons_client() {
+        let (mut client, _server) = client_with_server();
+
+        let result = client
+            .set_reader("foo", &b"short"[..], 10, None, None)
+            .await;
+        assert!(
+            matches!(result, Err(Error::Transport(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof),
+            "got {:?}",
+            result
+        );
+        assert!(client.is_poisoned());
+    }
+
+    #[tokio::test]
+    async fn test_failed_read_poisons_client() {
+        let (mut client, mut server) = client_with_server();
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let _ = server.read(&mut buf).await.unwrap();
+            server.write_all(b"VALUE foo 0 5\r\nhel").await.unwrap();
+        });
+
+        let result = client.get("foo").await;
+        assert!(
+            matches!(result, Err(Error::Transport(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof),
+            "got {:?}",
+            result
+        );
+        assert!(client.is_poisoned());
+
+        server_task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_completed_read_does_not_poison_client() {
+        let (mut client, mut server) = client_with_server();
+
+        let server_task = tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            let _ = server.read(&mut buf).await.unwrap();
+            server.write_all(b"END\r\n").await.unwrap();
+            server
+        });
+
+        let result = tokio::time::timeout(Duration::from_secs(5), client.get("foo")).await;
+        assert_eq!(Ok(Ok(None)), result.map_err(|_| ()));
+        assert!(!client.is_poisoned());
+
+        let _ = server_task.await;
+    }
+
+    #[tokio::test]
+    async fn test_increment_raw() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"18446744073709551616\r\nNOT_FOUND\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.increment_raw("foo", 1).await,
+            Ok(b"18446744073709551616".to_vec())
+        );
+        assert_eq!(
+            client.increment_raw("bar", 1).await,
+            Err(Error::Command(CommandError::NotFound))
+        );
+
+        server.write_all(b"18446744073709551616\r\n").await.unwrap();
+        assert_eq!(
+            client.increment("foo", 1).await,
+            Err(Error::Protocol(ErrorKind::Protocol(Some(
+                "unparseable incr result".to_string()
+            ))))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_default_ttl_and_flags_precedence() {
+        let (client, mut server) = client_with_server();
+        let mut client = client.with_default_ttl(3600).with_default_flags(7);
+
+        server
+            .write_all(b"STORED\r\nSTORED\r\nSTORED\r\n")
+            .await
+            .unwrap();
+        client.set("a", "1", None, None).await.unwrap();
+        client.set("b", "2", Some(60), Some(1)).await.unwrap();
+        client.add("c", "3", Some(0), Some(0)).await.unwrap();
+        drop(client);
+
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert_eq!(
+            written,
+            b"set a 7 3600 1\r\n1\r\nset b 1 60 1\r\n2\r\nadd c 0 0 1\r\n3\r\n"
+        );
+
+        let (mut client, mut server) = client_with_server();
+        server.write_all(b"STORED\r\n").await.unwrap();
+        client.set("a", "1", None, None).await.unwrap();
+        drop(client);
+
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert_eq!(written, b"set a 0 0 1\r\n1\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_reset_caches() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"MN\r\n").await.unwrap();
+        assert_eq!(client.supports_meta().await, Ok(true));
+        assert_eq!(client.supports_meta().await, Ok(true));
+
+        client.reset_caches();
+        server.write_all(b"ERROR\r\n").await.unwrap();
+        assert_eq!(client.supports_meta().await, Ok(false));
+        drop(client);
+
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert_eq!(written, b"mn\r\nmn\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_hit_ratio() {
+        let (mut client, mut server) = client_with_server();
+        assert_eq!(client.hit_ratio(), 0.0);
+
+        server
+            .write_all(b"VALUE a 0 1\r\n1\r\nEND\r\nEND\r\nVALUE b 0 1\r\n2\r\nEND\r\n")
+            .await
+            .unwrap();
+        assert!(client.get("a").await.unwrap().is_some());
+        assert!(client.get("x").await.unwrap().is_none());
+        assert_eq!(client.get_multi(&["b", "y"]).await.unwrap().len(), 1);
+
+        assert_eq!(client.get_hits(), 2);
+        assert_eq!(client.get_misses(), 2);
+        assert_eq!(client.hit_ratio(), 0.5);
+
+        client.reset_caches();
+        assert_eq!((client.get_hits(), client.get_misses()), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_connection_info() {
+        let (client, _server) = client_with_server();
+        let info = client.connection_info();
+        // A socket pair is unnamed, so there is no path, but it is still connected.
+        assert_eq!(info.socket_path, None);
+        assert_eq!(info.peer_addr, None);
+        assert!(info.connected);
+
+        let client = Client::from_stream(tokio::io::empty());
+        assert_eq!(
+            client.connection_info(),
+            ConnectionInfo {
+                connected: true,
+                ..Default::default()
             }
-            Ok(MetadumpResponse::Entry(km)) => Some(Ok(km)),
-            Err(e) => Some(Err(e)),
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dump_keys_with_timeout_is_resumable() {
+        let (mut client, mut server) = client_with_server();
+
+        let mut iter = client
+            .dump_keys_with_timeout(Duration::from_millis(50))
+            .await
+            .unwrap();
+        server
+            .write_all(b"key=foo exp=-1 la=1 cas=1 fetch=no cls=1 size=63\nkey=b")
+            .await
+            .unwrap();
+        assert_eq!(iter.next().await.unwrap().unwrap().key, b"foo".to_vec());
+        assert_eq!(
+            iter.next().await,
+            Some(Err(Error::Timeout {
+                operation: "metadump",
+                endpoint: "unknown endpoint".to_string(),
+                after: Duration::from_millis(50),
+            }))
+        );
+
+        server
+            .write_all(b"ar exp=-1 la=1 cas=2 fetch=no cls=1 size=63\nEND\r\n")
+            .await
+            .unwrap();
+        assert_eq!(iter.next().await.unwrap().unwrap().key, b"bar".to_vec());
+        assert_eq!(iter.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_set_chunked() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"STORED\r\n").await.unwrap();
+        let chunks: Vec<&[u8]> = vec![b"hello", b" ", b"world"];
+        client
+            .set_chunked("foo", chunks, 11, None, None)
+            .await
+            .unwrap();
+
+        let result = client.set_chunked("foo", ["abc"], 4, None, None).await;
+        assert!(
+            matches!(
+                result,
+                Err(Error::Transport(ref e)) if e.kind() == std::io::ErrorKind::InvalidInput
+            ),
+            "got {:?}",
+            result
+        );
+        assert!(!client.is_poisoned());
+        drop(client);
+
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert_eq!(written, b"set foo 0 0 11\r\nhello world\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_self_test() {
+        let (mut client, server) = client_with_server();
+        let server_task = tokio::spawn(run_mock_server(server));
+        assert_eq!(client.self_test().await, Ok(()));
+        drop(client);
+        server_task.await.unwrap();
+
+        let (mut client, mut server) = client_with_server();
+        server
+            .write_all(b"STORED\r\nVALUE x 0 3\r\nbad\r\nEND\r\nDELETED\r\n")
+            .await
+            .unwrap();
+        let result = client.self_test().await;
+        assert!(
+            matches!(
+                result,
+                Err(Error::Command(CommandError::Error(ErrorKind::Generic(_))))
+            ),
+            "got {:?}",
+            result
+        );
+        assert!(!client.has_pending_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_bad_command_format_poisons_client() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"CLIENT_ERROR bad command line format\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.set("foo bar", "baz", None, None).await,
+            Err(Error::Protocol(ErrorKind::BadCommandFormat))
+        );
+        assert!(client.is_poisoned());
+
+        let result = client.get("foo").await;
+        assert!(
+            matches!(result, Err(Error::Transport(_))),
+            "got {:?}",
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn test_append_too_large() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(
+                b"SERVER_ERROR object too large for cache\r\n\
+                  SERVER_ERROR out of memory storing object\r\nSTORED\r\n",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            client.try_append("foo", "bar").await,
+            Err(Error::Command(CommandError::ValueTooLarge(
+                "object too large for cache".to_string()
+            )))
+        );
+        assert_eq!(
+            client.try_append("foo", "bar").await,
+            Err(Error::Command(CommandError::Error(ErrorKind::Server(
+                "out of memory storing object".to_string()
+            ))))
+        );
+        assert_eq!(client.try_prepend("foo", "bar").await, Ok(true));
+    }
+
+    #[test]
+    fn test_is_storage_command_line() {
+        assert!(is_storage_command_line(b"set foo 0 0 3\r\n"));
+        assert!(is_storage_command_line(b"add foo 42 -1 3 noreply\r\n"));
+        // Keys are not validated, so a key with a space still makes for a well-formed line.
+        assert!(is_storage_command_line(b"set foo bar 1 60 3\r\n"));
+
+        assert!(!is_storage_command_line(b"set foo 0 0 3"));
+        assert!(!is_storage_command_line(b"set foo 0 03\r\n"));
+        assert!(!is_storage_command_line(b"set  0 0 3\r\n"));
+        assert!(!is_storage_command_line(b"setfoo 0 0 3\r\n"));
+        assert!(!is_storage_command_line(b"set foo 0 0 -3\r\n"));
+        assert!(!is_storage_command_line(b"set foo -0 x 3\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_max_line_bytes() {
+        let (client, mut server) = client_with_server();
+        let mut client = client.with_max_line_bytes(64);
+
+        // A value larger than the limit is fine, as its data is not a line.
+        let mut response = b"VALUE foo 0 256\r\n".to_vec();
+        response.extend_from_slice(&[b'a'; 256]);
+        response.extend_from_slice(b"\r\nEND\r\n");
+        server.write_all(&response).await.unwrap();
+        assert_eq!(client.get("foo").await.unwrap().unwrap().data.len(), 256);
+
+        server.write_all(b"VALUE ").await.unwrap();
+        server.write_all(&[b'k'; 128]).await.unwrap();
+        assert_eq!(
+            client.get("foo").await,
+            Err(Error::Protocol(ErrorKind::Protocol(Some(
+                "line too long".to_string()
+            ))))
+        );
+        assert!(client.is_poisoned());
+    }
+
+    #[tokio::test]
+    async fn test_stats_bundle() {
+        let (mut client, server) = client_with_server();
+
+        let server_task = tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            let responses: [&[u8]; 3] = [
+                b"STAT pid 1\r\nEND\r\n",
+                b"STAT maxconns 1024\r\nEND\r\n",
+                b"STAT items:1:number 5\r\nEND\r\n",
+            ];
+            let mut commands = Vec::new();
+            for response in responses.iter() {
+                let mut line = String::new();
+                server.read_line(&mut line).await.unwrap();
+                commands.push(line);
+                server.write_all(response).await.unwrap();
+            }
+            commands
+        });
+
+        let bundle = client.stats_bundle().await.unwrap();
+        assert_eq!(bundle.general.get("pid").map(String::as_str), Some("1"));
+        assert_eq!(
+            bundle.settings.get("maxconns").map(String::as_str),
+            Some("1024")
+        );
+        assert_eq!(
+            bundle.items.get("items:1:number").map(String::as_str),
+            Some("5")
+        );
+        assert_eq!(
+            server_task.await.unwrap(),
+            vec!["stats\r\n", "stats settings\r\n", "stats items\r\n"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_if_flags() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"HD f1 c5\r\nHD\r\nHD f2 c6\r\nEN\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.set_if_flags("foo", "bar", 1, Some(60), 2).await,
+            Ok(())
+        );
+        assert_eq!(
+            client.set_if_flags("foo", "bar", 1, None, 2).await,
+            Err(Error::Command(CommandError::FlagsMismatch {
+                expected: 1,
+                actual: 2
+            }))
+        );
+        assert_eq!(
+            client.set_if_flags("baz", "bar", 1, None, 2).await,
+            Err(Error::Command(CommandError::NotFound))
+        );
+        drop(client);
+
+        let mut written = Vec::new();
+        server.read_to_end(&mut written).await.unwrap();
+        assert_eq!(
+            written,
+            &b"mg foo f c\r\nms foo 3 T60 F2 C5 MS\r\nbar\r\nmg foo f c\r\nmg baz f c\r\n"[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_context() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"NOT_FOUND\r\nDELETED\r\n").await.unwrap();
+        let result = client.delete("foo").await.with_context("request-1");
+        assert_eq!(
+            result,
+            Err(Error::Context {
+                id: "request-1".to_string(),
+                source: Box::new(Error::Command(CommandError::NotFound)),
+            })
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "[request-1] command: not found"
+        );
+        assert_eq!(client.delete("foo").await.with_context("request-2"), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_with_options() {
+        let (client, _server) = client_with_server();
+        assert_eq!(client.options(), &ClientOptions::default());
+
+        let options = ClientOptions {
+            default_ttl: Some(60),
+            ..Default::default()
+        };
+        let client = client.with_options(options.clone()).with_default_flags(3);
+        assert_eq!(
+            client.options(),
+            &ClientOptions {
+                default_flags: Some(3),
+                ..options
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_bytes() {
+        let (mut client, mut server) = client_with_server();
+
+        let data = vec![b'a'; 64 * 1024];
+        let mut response = b"VALUE foo 0 65536\r\n".to_vec();
+        response.extend_from_slice(&data);
+        response.extend_from_slice(b"\r\nEND\r\nEND\r\nVALUE bar 0 3\r\nbaz\r\nEND\r\n");
+
+        let server_task = tokio::spawn(async move {
+            server.write_all(&response).await.unwrap();
+            server
+        });
+
+        let got = client.get_bytes("foo").await.unwrap().unwrap();
+        assert_eq!(client.get_bytes("missing").await, Ok(None));
+        assert_eq!(
+            client.get("bar").await.unwrap().map(|v| v.data),
+            Some(b"baz".to_vec())
+        );
+        // The data must be unaffected by the buffer being reused for later responses.
+        assert_eq!(got.data, data);
+        assert!(!client.has_pending_bytes());
+
+        let _ = server_task.await;
+    }
+
+    #[tokio::test]
+    async fn test_set_no_reply_read_your_writes() {
+        let (mut client, server) = client_with_server();
+        let server_task = tokio::spawn(run_mock_server(server));
+
+        for i in 0..10u32 {
+            let value = format!("value-{}", i);
+            client
+                .set_no_reply("foo", value.as_str(), None, Some(i))
+                .await
+                .unwrap();
+            let got = client.get("foo").await.unwrap().unwrap();
+            assert_eq!(got.data, value.as_bytes());
+            assert_eq!(got.flags, i);
+        }
+
+        drop(client);
+        let _ = server_task.await;
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let (mut client, mut server) = client_with_server();
+
+        assert_eq!(client.drain().await, Ok(0));
+
+        server
+            .write_all(b"VALUE foo 0 3\r\nbar\r\nEND\r\nstray bytes\r\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get("foo").await.unwrap().map(|v| v.data),
+            Some(b"bar".to_vec())
+        );
+        server.write_all(b"more\r\n").await.unwrap();
+        client.poisoned = true;
+
+        assert_eq!(client.drain().await, Ok(19));
+        assert!(!client.has_pending_bytes());
+        assert!(!client.is_poisoned());
+
+        let mut line = [0; 9];
+        server.read_exact(&mut line).await.unwrap();
+        assert_eq!(&line, b"get foo\r\n");
+        let server_task = tokio::spawn(run_mock_server(server));
+        client.set("foo", "baz", None, None).await.unwrap();
+        assert_eq!(
+            client.get("foo").await.unwrap().map(|v| v.data),
+            Some(b"baz".to_vec())
+        );
+
+        drop(client);
+        let _ = server_task.await;
+    }
+
+    #[tokio::test]
+    async fn test_set_multi_result_shape() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"STORED\r\nNOT_STORED\r\nSERVER_ERROR object too large for cache\r\n")
+            .await
+            .unwrap();
+
+        let kv = [("foo", "1"), ("bar", "2"), ("baz", "3")];
+        let results: FxHashMap<&&str, Result<(), Error>> =
+            client.add_multi(&kv, None, None).await.unwrap();
+
+        assert_eq!(results.len(), 3);
+        for (key, result) in results {
+            match (*key, result) {
+                ("foo", Ok(())) => {}
+                ("bar", Err(Error::Command(CommandError::NotStored))) => {}
+                ("baz", Err(Error::Command(CommandError::Error(ErrorKind::Server(msg))))) => {
+                    assert_eq!(msg, "object too large for cache")
+                }
+                (key, result) => panic!("unexpected result for {}: {:?}", key, result),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_touch_if_exists() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"TOUCHED\r\nNOT_FOUND\r\nTOUCHED\r\nNOT_FOUND\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(client.touch_if_exists("foo", 60).await, Ok(true));
+        assert_eq!(client.touch_if_exists("bar", 60).await, Ok(false));
+        assert_eq!(client.touch("foo", 60).await, Ok(()));
+        assert_eq!(
+            client.touch("bar", 60).await,
+            Err(Error::Command(CommandError::NotFound))
+        );
+
+        assert_eq!(client.touch_no_reply("foo", 30).await, Ok(()));
+
+        let expected =
+            b"touch foo 60\r\ntouch bar 60\r\ntouch foo 60\r\ntouch bar 60\r\ntouch foo 30 noreply\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[cfg(feature = "testing")]
+    #[tokio::test]
+    async fn test_fault_injector() {
+        use crate::{Fault, FaultInjector};
+
+        let (client, server) = client_with_server();
+        let server_task = tokio::spawn(run_mock_server(server));
+        let mut client = client
+            .wrap_stream(|stream| {
+                FaultInjector::new(stream)
+                    .on(
+                        "set",
+                        |n| n == 3,
+                        Fault::Error(std::io::ErrorKind::ConnectionReset),
+                    )
+                    .on("get", |n| n == 2, Fault::Delay(Duration::from_millis(50)))
+            })
+            .await
+            .unwrap();
+
+        for i in 1..=2 {
+            // Values which look like commands must not be counted as commands.
+            let result = client
+                .set(format!("key{}", i), "set foo 0 0 1", None, None)
+                .await;
+            assert_eq!(result, Ok(()), "set {} failed", i);
+        }
+
+        assert!(client.get("key1").await.unwrap().is_some());
+        let start = std::time::Instant::now();
+        assert!(client.get("key2").await.unwrap().is_some());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let result = client.set("key3", "set foo 0 0 1", None, None).await;
+        assert!(
+            matches!(&result, Err(Error::Transport(e)) if e.kind() == std::io::ErrorKind::ConnectionReset),
+            "set 3 was not failed: {:?}",
+            result
+        );
+        // The injected error fails the read of the response, so the client cannot be reused.
+        assert!(client.is_poisoned());
+
+        drop(client);
+        let _ = server_task.await;
+    }
+
+    #[tokio::test]
+    async fn test_item_count() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(
+                b"STAT curr_items 42\r\nSTAT total_items 100\r\nEND\r\nSTAT pid 1\r\nEND\r\n",
+            )
+            .await
+            .unwrap();
+        server
+            .write_all(b"STAT curr_items 42\r\nSTAT total_items 100\r\nEND\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(client.item_count().await, Ok(42));
+        assert_eq!(
+            client.item_count().await,
+            Err(Error::Protocol(ErrorKind::Protocol(Some(
+                "missing or invalid stat `curr_items`".to_string()
+            ))))
+        );
+        assert_eq!(client.total_items().await, Ok(100));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"HD\r\nNF\r\nEX\r\n").await.unwrap();
+
+        assert_eq!(client.invalidate("foo", 30).await, Ok(true));
+        assert_eq!(client.invalidate("bar", 30).await, Ok(false));
+        assert_eq!(
+            client
+                .meta_delete("foo", &DeleteFlags::new().compare_cas(5))
+                .await,
+            Err(Error::Command(CommandError::Exists))
+        );
+
+        let expected = b"md foo I T30\r\nmd bar I T30\r\nmd foo C5\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_get_multi_all_miss() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"END\r\nEND\r\nEND\r\nVALUE foo 0 3\r\nbar\r\nEND\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(client.get_multi(&["a", "b"]).await, Ok(Vec::new()));
+        assert_eq!(
+            client.get_multi_pipelined(&["a", "b"], 2).await,
+            Ok(Vec::new())
+        );
+        let (hits, misses) = client.get_multi_with_misses(&["a"]).await.unwrap();
+        assert!(hits.is_empty());
+        assert_eq!(misses, vec![b"a".to_vec()]);
+
+        // The bare end markers are fully consumed, so the next command reads its own response.
+        assert_eq!(
+            client.get("foo").await.unwrap().map(|v| v.data),
+            Some(b"bar".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_buffer_high_water() {
+        let (mut client, server) = client_with_server();
+        client.set_read_buffer_high_water(16 * 1024);
+        let server_task = tokio::spawn(run_mock_server(server));
+
+        let large = vec![b'a'; 512 * 1024];
+        client.set("large", &large[..], None, None).await.unwrap();
+        client.set("small", "value", None, None).await.unwrap();
+
+        let value = client.get("large").await.unwrap().unwrap();
+        assert_eq!(value.data, large);
+        assert!(client.buf.capacity() >= large.len());
+
+        let value = client.get("small").await.unwrap().unwrap();
+        assert_eq!(value.data, b"value");
+        assert!(
+            client.buf.capacity() <= 16 * 1024,
+            "buffer kept {} bytes",
+            client.buf.capacity()
+        );
+
+        drop(client);
+        let _ = server_task.await;
+    }
+
+    #[tokio::test]
+    async fn test_get_multi_max_command_bytes() {
+        let (client, server) = client_with_server();
+        let mut client = client.with_max_command_bytes(64);
+
+        let server_task = tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            let mut lines = Vec::new();
+            loop {
+                let mut line = Vec::new();
+                if server.read_until(b'\n', &mut line).await.unwrap() == 0 {
+                    return lines;
+                }
+                let mut response = Vec::new();
+                for key in line[4..line.len() - 2].split(|b| *b == b' ') {
+                    response.extend_from_slice(b"VALUE ");
+                    response.extend_from_slice(key);
+                    response.extend_from_slice(format!(" 0 {}\r\n", key.len()).as_bytes());
+                    response.extend_from_slice(key);
+                    response.extend_from_slice(b"\r\n");
+                }
+                response.extend_from_slice(b"END\r\n");
+                server.write_all(&response).await.unwrap();
+                lines.push(line);
+            }
+        });
+
+        let keys: Vec<String> = (0..10)
+            .map(|i| format!("a-rather-long-key-{:02}", i))
+            .collect();
+        let values = client.get_multi(&keys).await.unwrap();
+        let found: Vec<Vec<u8>> = values.into_iter().map(|v| v.data).collect();
+        let expected: Vec<Vec<u8>> = keys.iter().map(|k| k.as_bytes().to_vec()).collect();
+        assert_eq!(found, expected);
+        assert_eq!(client.get_hits(), 10);
+
+        drop(client);
+        let lines = server_task.await.unwrap();
+        assert!(lines.len() > 1, "keys were not split: {:?}", lines);
+        for line in &lines {
+            assert!(line.len() <= 64, "command too long: {:?}", line);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_command_error_categories() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"NOT_FOUND\r\nNOT_STORED\r\nSERVER_ERROR out of memory\r\nbogus\r\n")
+            .await
+            .unwrap();
+
+        let err = client.touch("foo", 60).await.unwrap_err();
+        assert_eq!(err, Error::Command(CommandError::NotFound));
+        assert!(err.is_command());
+        let err = client.add("foo", "bar", None, None).await.unwrap_err();
+        assert_eq!(err, Error::Command(CommandError::NotStored));
+        assert!(err.is_command());
+        let err = client.set("foo", "bar", None, None).await.unwrap_err();
+        assert_eq!(
+            err,
+            Error::Command(CommandError::Error(ErrorKind::Server(
+                "out of memory".to_string()
+            )))
+        );
+        assert!(err.is_command());
+        let err = client.delete("foo").await.unwrap_err();
+        assert!(
+            matches!(err, Error::Protocol(ErrorKind::Protocol(_))),
+            "got {:?}",
+            err
+        );
+        assert!(err.is_transport());
+
+        drop(server);
+        let (mut client, server) = client_with_server();
+        drop(server);
+        let err = client.get("foo").await.unwrap_err();
+        assert!(matches!(err, Error::Transport(_)), "got {:?}", err);
+        assert!(err.is_transport());
+    }
+
+    #[tokio::test]
+    async fn test_gets() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(
+                b"VALUE foo 42 3 15\r\nbar\r\nEND\r\nEND\r\nVALUE foo 42 3\r\nbar\r\nEND\r\n",
+            )
+            .await
+            .unwrap();
+
+        let value = client.gets("foo").await.unwrap().unwrap();
+        assert_eq!(value.cas(), Some(15));
+        assert_eq!(value.flags, 42);
+        assert_eq!(value.data, b"bar");
+        assert_eq!(client.gets("missing").await, Ok(None));
+        assert_eq!(client.get("foo").await.unwrap().unwrap().cas(), None);
+
+        let expected = b"gets foo\r\ngets missing\r\nget foo\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_cas() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"STORED\r\nEXISTS\r\nNOT_FOUND\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            client.cas("foo", "bar", Some(60), Some(3), 15).await,
+            Ok(())
+        );
+        assert_eq!(
+            client.cas("foo", "bar", None, None, 15).await,
+            Err(Error::Command(CommandError::Exists))
+        );
+        assert_eq!(
+            client.cas("baz", "bar", None, None, 15).await,
+            Err(Error::Command(CommandError::NotFound))
+        );
+
+        let expected =
+            b"cas foo 3 60 3 15\r\nbar\r\ncas foo 0 0 3 15\r\nbar\r\ncas baz 0 0 3 15\r\nbar\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_append_prepend() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"STORED\r\nNOT_STORED\r\nSTORED\r\nNOT_STORED\r\n")
+            .await
+            .unwrap();
+
+        assert_eq!(client.append("foo", "bar").await, Ok(()));
+        assert_eq!(
+            client.append("missing", "bar").await,
+            Err(Error::Command(CommandError::NotStored))
+        );
+        assert_eq!(client.prepend("foo", "bar").await, Ok(()));
+        assert_eq!(
+            client.prepend("missing", "bar").await,
+            Err(Error::Command(CommandError::NotStored))
+        );
+
+        // The flags and TTL are ignored by the server, but must still be sent.
+        let expected = b"append foo 0 0 3\r\nbar\r\nappend missing 0 0 3\r\nbar\r\nprepend foo 0 0 3\r\nbar\r\nprepend missing 0 0 3\r\nbar\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_replace() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"STORED\r\nNOT_STORED\r\n").await.unwrap();
+
+        assert_eq!(
+            client.replace("foo", "bar", Some(60), Some(3)).await,
+            Ok(())
+        );
+        assert_eq!(
+            client.replace("missing", "bar", None, None).await,
+            Err(Error::Command(CommandError::NotStored))
+        );
+        assert_eq!(
+            client.replace_no_reply("foo", "baz", None, None).await,
+            Ok(())
+        );
+
+        let expected = b"replace foo 3 60 3\r\nbar\r\nreplace missing 0 0 3\r\nbar\r\nreplace foo 0 0 3 noreply\r\nbaz\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_gat() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"VALUE foo 42 3\r\nbar\r\nEND\r\nEND\r\nVALUE foo 42 3 15\r\nbar\r\nEND\r\nNOT_FOUND\r\n")
+            .await
+            .unwrap();
+
+        let value = client.gat(60, "foo").await.unwrap().unwrap();
+        assert_eq!(value.data, b"bar");
+        assert_eq!(value.cas, None);
+        assert_eq!(client.gat(60, "missing").await, Ok(None));
+        assert_eq!(client.gats(60, "foo").await.unwrap().unwrap().cas, Some(15));
+        assert_eq!(client.gats(60, "missing").await, Ok(None));
+
+        let expected = b"gat 60 foo\r\ngat 60 missing\r\ngats 60 foo\r\ngats 60 missing\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_flush_all() {
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"OK\r\nOK\r\nERROR\r\n").await.unwrap();
+
+        assert_eq!(client.flush_all().await, Ok(()));
+        assert_eq!(client.flush_all_delayed(30).await, Ok(()));
+        assert_eq!(client.flush_all_no_reply(Some(10)).await, Ok(()));
+        assert_eq!(client.flush_all_no_reply(None).await, Ok(()));
+        assert_eq!(
+            client.flush_all().await,
+            Err(Error::Command(CommandError::Error(
+                ErrorKind::NonexistentCommand
+            )))
+        );
+
+        let expected = b"flush_all\r\nflush_all 30\r\nflush_all 10 noreply\r\nflush_all noreply\r\nflush_all\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_get_multi_map() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"VALUE foo 0 3\r\nbar\r\nVALUE baz 0 4\r\nquux\r\nEND\r\n")
+            .await
+            .unwrap();
+
+        let values = client
+            .get_multi_map(&["foo", "missing", "baz"])
+            .await
+            .unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[b"foo".as_slice()].data, b"bar");
+        assert_eq!(values[b"baz".as_slice()].data, b"quux");
+        assert!(!values.contains_key(b"missing".as_slice()));
+
+        let expected = b"get foo missing baz\r\n";
+        let mut command = vec![0; expected.len()];
+        server.read_exact(&mut command).await.unwrap();
+        assert_eq!(command, expected);
+    }
+
+    #[tokio::test]
+    async fn test_delete_multi() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"DELETED\r\nNOT_FOUND\r\nDELETED\r\n")
+            .await
+            .unwrap();
+
+        let keys = ["foo", "missing", "baz"];
+        let results = client.delete_multi(keys).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results["missing"],
+            Err(Error::Command(CommandError::NotFound))
+        );
+
+        let expected = b"delete foo\r\ndelete missing\r\ndelete baz\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_touch_multi() {
+        let (mut client, mut server) = client_with_server();
+
+        server
+            .write_all(b"TOUCHED\r\nNOT_FOUND\r\nERROR\r\n")
+            .await
+            .unwrap();
+
+        let keys = ["foo", "missing", "bad"];
+        let results = client.touch_multi(keys, 60).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results["missing"], Ok(false));
+        assert_eq!(
+            results["bad"],
+            Err(Error::Command(CommandError::Error(
+                ErrorKind::NonexistentCommand
+            )))
+        );
+
+        let expected = b"touch foo 60\r\ntouch missing 60\r\ntouch bad 60\r\n";
+        let mut commands = vec![0; expected.len()];
+        server.read_exact(&mut commands).await.unwrap();
+        assert_eq!(commands, expected);
+    }
+
+    #[tokio::test]
+    async fn test_client_builder() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dsn = format!("tcp://{}", listener.local_addr().unwrap());
+
+        let client = Client::new(&dsn).await.unwrap();
+        assert_eq!(client.read_chunk_size(), 1024);
+        match &client.conn {
+            Connection::Tcp(s) => assert!(s.get_ref().get_ref().nodelay().unwrap()),
+            _ => panic!("expected a TCP connection"),
+        }
+
+        let client = Client::builder()
+            .tcp_nodelay(false)
+            .read_buffer_capacity(64 * 1024)
+            .connect_timeout(Duration::from_secs(5))
+            .connect(&dsn)
+            .await
+            .unwrap();
+        assert_eq!(client.read_chunk_size(), 64 * 1024);
+        assert_eq!(client.options().read_buffer_capacity, Some(64 * 1024));
+        match &client.conn {
+            Connection::Tcp(s) => assert!(!s.get_ref().get_ref().nodelay().unwrap()),
+            _ => panic!("expected a TCP connection"),
+        }
+    }
+
+    #[cfg(feature = "socks")]
+    #[tokio::test]
+    async fn test_client_builder_proxy() {
+        use super::ProxyConfig;
+        use tokio::net::TcpListener;
+
+        // Accepts a single unauthenticated SOCKS5 connect request for a domain, and then holds the
+        // tunnel open without forwarding it anywhere.
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = proxy.local_addr().unwrap().to_string();
+        let proxy_task = tokio::spawn(async move {
+            let (mut s, _) = proxy.accept().await.unwrap();
+            let mut greeting = [0u8; 3];
+            s.read_exact(&mut greeting).await.unwrap();
+            s.write_all(&[5, 0]).await.unwrap();
+            let mut request = [0u8; 5];
+            s.read_exact(&mut request).await.unwrap();
+            let mut target = vec![0u8; request[4] as usize + 2];
+            s.read_exact(&mut target).await.unwrap();
+            s.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 0])
+                .await
+                .unwrap();
+            (s, target)
+        });
+
+        let client = Client::builder()
+            .tcp_nodelay(false)
+            .proxy(ProxyConfig::new(proxy_addr))
+            .connect("tcp://memcached.internal:11211")
+            .await
+            .unwrap();
+        match &client.conn {
+            Connection::Tcp(s) => assert!(!s.get_ref().get_ref().nodelay().unwrap()),
+            _ => panic!("expected a TCP connection"),
         }
+        let (_, target) = proxy_task.await.unwrap();
+        assert_eq!(&target[..target.len() - 2], b"memcached.internal");
+
+        // A proxy which never completes the handshake is bounded by the connect timeout.
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let result = Client::builder()
+            .connect_timeout(Duration::from_millis(50))
+            .proxy(ProxyConfig::new(proxy.local_addr().unwrap().to_string()))
+            .connect("tcp://memcached.internal:11211")
+            .await;
+        assert!(matches!(
+            result,
+            Err(Error::Timeout {
+                operation: "connect",
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_operation_timeout() {
+        let (client, mut server) = client_with_server();
+        let mut client = client.with_options(ClientOptions {
+            operation_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+
+        let result = client.get("foo").await;
+        assert!(matches!(
+            result,
+            Err(Error::Timeout {
+                operation: "read",
+                ..
+            })
+        ));
+        assert!(client.is_poisoned());
+
+        server.write_all(b"END\r\n").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(client.drain().await, Ok(5));
+
+        // Each read is given its own timeout, so a response arriving in pieces is read in full
+        // even if it takes longer than the timeout overall.
+        let reader = client.get("foo");
+        let writer = async {
+            server.write_all(b"VALUE foo 0 3\r\n").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            server.write_all(b"bar\r\n").await.unwrap();
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            server.write_all(b"END\r\n").await.unwrap();
+        };
+        let (value, ()) = tokio::join!(reader, writer);
+        assert_eq!(value.unwrap().unwrap().data, b"bar");
     }
 }
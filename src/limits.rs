@@ -0,0 +1,75 @@
+use fxhash::FxHashMap;
+
+/// Maximum length of a key, in bytes, as enforced by memcached.
+pub(crate) const MAX_KEY_LENGTH: usize = 250;
+
+/// Default maximum size of an item, in bytes, used when the server does not report `item_size_max`.
+pub(crate) const DEFAULT_ITEM_SIZE_MAX: u64 = 1024 * 1024;
+
+/// Largest TTL, in seconds, which memcached treats as relative to the current time.  Any larger
+/// TTL is treated as an absolute Unix timestamp.
+pub(crate) const MAX_RELATIVE_TTL: i64 = 60 * 60 * 24 * 30;
+
+/// Thresholds enforced when storing items.
+///
+/// The maximum key length and relative TTL are fixed by the protocol, while the maximum value size
+/// is read from the server's `stats settings`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length of a key, in bytes.
+    pub max_key_length: usize,
+    /// Maximum size of an item, in bytes (`item_size_max`).
+    ///
+    /// This bounds the key, value and item overhead combined, so the largest storable value is
+    /// slightly smaller.
+    pub max_value_size: u64,
+    /// Largest TTL, in seconds, treated as relative to the current time.
+    ///
+    /// Larger TTLs are treated by the server as an absolute Unix timestamp.
+    pub max_relative_ttl: i64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_key_length: MAX_KEY_LENGTH,
+            max_value_size: DEFAULT_ITEM_SIZE_MAX,
+            max_relative_ttl: MAX_RELATIVE_TTL,
+        }
+    }
+}
+
+impl Limits {
+    /// Builds the limits from the raw output of `stats settings`.
+    pub(crate) fn from_settings(settings: &FxHashMap<String, String>) -> Self {
+        let mut limits = Limits::default();
+        if let Some(size) = settings
+            .get("item_size_max")
+            .and_then(|v| v.parse::<u64>().ok())
+        {
+            limits.max_value_size = size;
+        }
+        limits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Limits, DEFAULT_ITEM_SIZE_MAX};
+    use fxhash::FxHashMap;
+
+    #[test]
+    fn test_limits_from_settings() {
+        let mut settings = FxHashMap::default();
+        assert_eq!(Limits::from_settings(&settings), Limits::default());
+        assert_eq!(
+            Limits::from_settings(&settings).max_value_size,
+            DEFAULT_ITEM_SIZE_MAX
+        );
+
+        settings.insert("item_size_max".to_string(), "2097152".to_string());
+        let limits = Limits::from_settings(&settings);
+        assert_eq!(limits.max_value_size, 2097152);
+        assert_eq!(limits.max_key_length, 250);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{Client, Error};
+use std::time::Duration;
+
+/// Manages the clients of a connection pool, for use with `bb8` or `deadpool`.
+///
+/// Each client is connected to the same server, with [`Client::new`].  Clients which are poisoned
+/// or still have unread bytes from a cancelled or panicked command are discarded rather than
+/// reused, so that a command which fails part way through cannot hand its response to the next
+/// user of the pool.  Before a client is handed out again, it is also checked with
+/// [`Client::sync`], unless [`ClientManager::with_max_idle`] is set and the client has been used
+/// more recently than that.
+#[derive(Clone, Debug)]
+pub struct ClientManager {
+    dsn: String,
+    max_idle: Option<Duration>,
+}
+
+impl ClientManager {
+    /// Creates a manager for clients connected to the given DSN, as accepted by [`Client::new`].
+    pub fn new<S: Into<String>>(dsn: S) -> ClientManager {
+        ClientManager {
+            dsn: dsn.into(),
+            max_idle: None,
+        }
+    }
+
+    /// Only checks clients with [`Client::sync`] once they have been idle for longer than
+    /// `max_idle`.
+    ///
+    /// A client which has been used recently is handed out without a round trip to the server,
+    /// while one which has been idle for longer, and so may have been closed by the server's
+    /// `idle_timeout`, is checked first.  `max_idle` should be well below the server's
+    /// `idle_timeout`.  By default, every client is checked.
+    pub fn with_max_idle(mut self, max_idle: Duration) -> ClientManager {
+        self.max_idle = Some(max_idle);
+        self
+    }
+
+    /// Whether the client must be discarded rather than returned to the pool.
+    fn is_dirty(client: &Client) -> bool {
+        client.is_poisoned() || client.has_pending_bytes()
+    }
+
+    async fn check(&self, client: &mut Client) -> Result<(), Error> {
+        if Self::is_dirty(client) {
+            return Err(Error::Transport(std::io::Error::other(
+                "client is poisoned or has unread bytes from a previous command",
+            )));
+        }
+
+        match self.max_idle {
+            Some(max_idle) if client.idle_time() <= max_idle => Ok(()),
+            _ => client.sync().await,
+        }
+    }
+}
+
+#[cfg(feature = "bb8")]
+impl bb8::ManageConnection for ClientManager {
+    type Connection = Client;
+    type Error = Error;
+
+    async fn connect(&self) -> Result<Client, Error> {
+        Client::new(&self.dsn).await
+    }
+
+    async fn is_valid(&self, client: &mut Client) -> Result<(), Error> {
+        self.check(client).await
+    }
+
+    fn has_broken(&self, client: &mut Client) -> bool {
+        Self::is_dirty(client)
+    }
+}
+
+#[cfg(feature = "deadpool")]
+impl deadpool::managed::Manager for ClientManager {
+    type Type = Client;
+    type Error = Error;
+
+    async fn create(&self) -> Result<Client, Error> {
+        Client::new(&self.dsn).await
+    }
+
+    async fn recycle(
+        &self,
+        client: &mut Client,
+        _: &deadpool::managed::Metrics,
+    ) -> deadpool::managed::RecycleResult<Error> {
+        Ok(self.check(client).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientManager;
+    use crate::{connection::Connection, Client, Error};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+    use tokio::net::UnixStream;
+
+    fn client_with_server() -> (Client, UnixStream) {
+        let (client, server) = UnixStream::pair().expect("failed to create socket pair");
+        let conn = Connection::Unix(BufReader::new(BufWriter::new(client)));
+        (Client::from_connection(conn), server)
+    }
+
+    #[tokio::test]
+    async fn test_check_syncs_client() {
+        let manager = ClientManager::new("tcp://127.0.0.1:11211");
+        let (mut client, mut server) = client_with_server();
+
+        server.write_all(b"VERSION 1.6.21\r\n").await.unwrap();
+        assert_eq!(manager.check(&mut client).await, Ok(()));
+
+        let mut command = [0; 9];
+        server.read_exact(&mut command).await.unwrap();
+        assert_eq!(&command, b"version\r\n");
+
+        // Without a server to answer the `version` command, the client is rejected.
+        drop(server);
+        assert!(manager.check(&mut client).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_skips_recently_used_client() {
+        let manager =
+            ClientManager::new("tcp://127.0.0.1:11211").with_max_idle(Duration::from_secs(60));
+        let (mut client, server) = client_with_server();
+
+        // A recently used client is handed out without a round trip to the server.
+        drop(server);
+        assert_eq!(manager.check(&mut client).await, Ok(()));
+
+        let manager = manager.with_max_idle(Duration::ZERO);
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert!(manager.check(&mut client).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_rejects_dirty_client() {
+        let manager =
+            ClientManager::new("tcp://127.0.0.1:11211").with_max_idle(Duration::from_secs(60));
+
+        // A command abandoned part way through its response, e.g. by a panicking task, leaves the
+        // client poisoned.
+        let (mut client, _server) = client_with_server();
+        let _ = tokio::time::timeout(Duration::from_millis(10), client.get("foo")).await;
+        assert!(ClientManager::is_dirty(&client));
+        assert!(matches!(
+            manager.check(&mut client).await,
+            Err(Error::Transport(_))
+        ));
+
+        // Bytes beyond the last response would be read as the response to the next command.
+        let (mut client, mut server) = client_with_server();
+        server.write_all(b"END\r\nEND\r\n").await.unwrap();
+        assert_eq!(client.get("foo").await, Ok(None));
+        assert!(ClientManager::is_dirty(&client));
+        assert!(matches!(
+            manager.check(&mut client).await,
+            Err(Error::Transport(_))
+        ));
+    }
+}
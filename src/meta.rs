@@ -0,0 +1,718 @@
+use btoi::{btoi, btou};
+use std::borrow::Cow;
+
+use crate::Value;
+
+/// Flags for a meta command, as written to the server.
+///
+/// This is only built through the builder for each command, i.e. [`GetFlags`], [`StoreFlags`],
+/// [`DeleteFlags`] and [`ArithmeticFlags`], each of which only exposes the flags that its command
+/// accepts, so flags which don't apply to a command cannot be written with it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct MetaFlags {
+    want_value: bool,
+    want_flags: bool,
+    want_cas: bool,
+    want_ttl: bool,
+    want_key: bool,
+    ttl: Option<i64>,
+    client_flags: Option<u32>,
+    autovivify_ttl: Option<i64>,
+    recache_ttl: Option<i64>,
+    initial_value: Option<u64>,
+    compare_cas: Option<u64>,
+    base64_key: bool,
+    invalidate: bool,
+    opaque: Option<u32>,
+}
+
+impl MetaFlags {
+    pub(crate) fn want_value(mut self) -> Self {
+        self.want_value = true;
+        self
+    }
+
+    fn want_flags(mut self) -> Self {
+        self.want_flags = true;
+        self
+    }
+
+    fn want_cas(mut self) -> Self {
+        self.want_cas = true;
+        self
+    }
+
+    fn want_ttl(mut self) -> Self {
+        self.want_ttl = true;
+        self
+    }
+
+    fn want_key(mut self) -> Self {
+        self.want_key = true;
+        self
+    }
+
+    fn set_ttl(mut self, ttl: i64) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    fn set_flags(mut self, flags: u32) -> Self {
+        self.client_flags = Some(flags);
+        self
+    }
+
+    fn autovivify(mut self, ttl: i64) -> Self {
+        self.autovivify_ttl = Some(ttl);
+        self
+    }
+
+    fn recache(mut self, ttl: i64) -> Self {
+        self.recache_ttl = Some(ttl);
+        self
+    }
+
+    fn initial_value(mut self, value: u64) -> Self {
+        self.initial_value = Some(value);
+        self
+    }
+
+    fn compare_cas(mut self, cas: u64) -> Self {
+        self.compare_cas = Some(cas);
+        self
+    }
+
+    fn base64_key(mut self) -> Self {
+        self.base64_key = true;
+        self
+    }
+
+    fn invalidate(mut self) -> Self {
+        self.invalidate = true;
+        self
+    }
+
+    fn opaque(mut self, token: u32) -> Self {
+        self.opaque = Some(token);
+        self
+    }
+
+    /// Returns the key as it should be written for a meta command, along with the flags to write
+    /// with it.
+    ///
+    /// Keys containing whitespace or control characters cannot be written as-is, so they are
+    /// base64-encoded and the `b` flag is added.
+    pub(crate) fn encode_key<'k>(&self, key: &'k [u8]) -> (Cow<'k, [u8]>, Cow<'_, MetaFlags>) {
+        if self.base64_key || !key.iter().any(|b| *b <= b' ' || *b == 127) {
+            return (Cow::Borrowed(key), Cow::Borrowed(self));
+        }
+
+        let encoded = base64_encode(key);
+        (Cow::Owned(encoded), Cow::Owned(self.clone().base64_key()))
+    }
+
+    /// Writes the protocol tokens for these flags, each preceded by a space.
+    pub(crate) fn write_tokens(&self, out: &mut Vec<u8>) {
+        let switches = [
+            (self.base64_key, b'b'),
+            (self.want_value, b'v'),
+            (self.want_flags, b'f'),
+            (self.want_cas, b'c'),
+            (self.want_ttl, b't'),
+            (self.want_key, b'k'),
+            (self.invalidate, b'I'),
+        ];
+        for (enabled, token) in switches.iter() {
+            if *enabled {
+                out.extend_from_slice(&[b' ', *token]);
+            }
+        }
+
+        if let Some(ttl) = self.ttl {
+            out.extend_from_slice(b" T");
+            out.extend_from_slice(ttl.to_string().as_bytes());
+        }
+        if let Some(flags) = self.client_flags {
+            out.extend_from_slice(b" F");
+            out.extend_from_slice(flags.to_string().as_bytes());
+        }
+        if let Some(ttl) = self.autovivify_ttl {
+            out.extend_from_slice(b" N");
+            out.extend_from_slice(ttl.to_string().as_bytes());
+        }
+        if let Some(ttl) = self.recache_ttl {
+            out.extend_from_slice(b" R");
+            out.extend_from_slice(ttl.to_string().as_bytes());
+        }
+        if let Some(value) = self.initial_value {
+            out.extend_from_slice(b" J");
+            out.extend_from_slice(value.to_string().as_bytes());
+        }
+        if let Some(cas) = self.compare_cas {
+            out.extend_from_slice(b" C");
+            out.extend_from_slice(cas.to_string().as_bytes());
+        }
+        if let Some(token) = self.opaque {
+            out.extend_from_slice(b" O");
+            out.extend_from_slice(token.to_string().as_bytes());
+        }
+    }
+}
+
+/// Implements a builder for the flags of a single meta command, forwarding each of the given
+/// methods to [`MetaFlags`].
+macro_rules! meta_flags_builder {
+    ($builder:ident { $($(#[$doc:meta])* $method:ident($($arg:ident: $ty:ty)?);)* }) => {
+        impl $builder {
+            /// Creates an empty set of flags.
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            $(
+                $(#[$doc])*
+                pub fn $method(self, $($arg: $ty)?) -> Self {
+                    $builder(self.0.$method($($arg)?))
+                }
+            )*
+        }
+    };
+}
+
+/// Flags for [`Client::meta_get`](crate::Client::meta_get).
+///
+/// Each method adds a single flag, and the flags are rendered to their protocol tokens when the
+/// command is written, so callers never need to assemble raw flag strings.  Only the flags which
+/// `mg` accepts are available, so e.g. a CAS comparison cannot be sent with a get.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GetFlags(pub(crate) MetaFlags);
+
+meta_flags_builder!(GetFlags {
+    /// Requests the value's data (`v`).
+    want_value();
+    /// Requests the value's client flags (`f`).
+    want_flags();
+    /// Requests the value's CAS identifier (`c`).
+    want_cas();
+    /// Requests the value's remaining TTL, in seconds (`t`).
+    want_ttl();
+    /// Requests the key to be echoed back (`k`).
+    want_key();
+    /// Updates the TTL of the key, in seconds (`T`).
+    set_ttl(ttl: i64);
+    /// Creates the key if it does not exist, with the given TTL in seconds (`N`).
+    ///
+    /// The first client to miss wins the right to compute the value, as reported by
+    /// [`MetaValue::recache`].
+    autovivify(ttl: i64);
+    /// Wins the right to recache the key if its remaining TTL is below the given number of
+    /// seconds (`R`).
+    ///
+    /// Along with [`GetFlags::autovivify`], this lets only one client recompute a value which is
+    /// missing or about to expire, as reported by [`MetaValue::recache`].
+    recache(ttl: i64);
+    /// Marks the key as base64-encoded (`b`).
+    ///
+    /// This only needs to be set for keys which are already encoded, as keys containing bytes
+    /// which cannot be sent as-is are encoded automatically.
+    base64_key();
+    /// Sets an opaque token, which the server echoes back in its response (`O`).
+    ///
+    /// The echoed token is returned in [`MetaValue::opaque`].
+    opaque(token: u32);
+});
+
+/// Flags for [`Client::meta_store`](crate::Client::meta_store).
+///
+/// Only the flags which `ms` accepts are available.  The data, flags and TTL of the stored value
+/// cannot be requested, as they are those which were just set.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StoreFlags(pub(crate) MetaFlags);
+
+meta_flags_builder!(StoreFlags {
+    /// Requests the CAS identifier of the stored value (`c`).
+    want_cas();
+    /// Requests the key to be echoed back (`k`).
+    want_key();
+    /// Sets the TTL of the key, in seconds (`T`).
+    set_ttl(ttl: i64);
+    /// Sets the client flags of the key (`F`).
+    set_flags(flags: u32);
+    /// Only stores the value if the key's CAS identifier matches the given one (`C`).
+    compare_cas(cas: u64);
+    /// Marks the stored value as stale if the CAS identifier given with
+    /// [`StoreFlags::compare_cas`] is older than the key's, rather than failing (`I`).
+    invalidate();
+    /// Marks the key as base64-encoded (`b`).
+    ///
+    /// This only needs to be set for keys which are already encoded, as keys containing bytes
+    /// which cannot be sent as-is are encoded automatically.
+    base64_key();
+    /// Sets an opaque token, which the server echoes back in its response (`O`).
+    opaque(token: u32);
+});
+
+/// Flags for [`Client::meta_delete`](crate::Client::meta_delete).
+///
+/// Only the flags which `md` accepts are available.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeleteFlags(pub(crate) MetaFlags);
+
+meta_flags_builder!(DeleteFlags {
+    /// Requests the key to be echoed back (`k`).
+    want_key();
+    /// Sets the TTL of the key, in seconds, when it is marked as stale with
+    /// [`DeleteFlags::invalidate`] (`T`).
+    set_ttl(ttl: i64);
+    /// Only deletes the key if its CAS identifier matches the given one (`C`).
+    compare_cas(cas: u64);
+    /// Marks the key as stale rather than deleting it (`I`), as done by
+    /// [`Client::invalidate`](crate::Client::invalidate).
+    invalidate();
+    /// Marks the key as base64-encoded (`b`).
+    ///
+    /// This only needs to be set for keys which are already encoded, as keys containing bytes
+    /// which cannot be sent as-is are encoded automatically.
+    base64_key();
+    /// Sets an opaque token, which the server echoes back in its response (`O`).
+    opaque(token: u32);
+});
+
+/// Flags for [`Client::meta_arithmetic`](crate::Client::meta_arithmetic).
+///
+/// Only the flags which `ma` accepts are available.  The value of the counter is always
+/// requested.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArithmeticFlags(pub(crate) MetaFlags);
+
+meta_flags_builder!(ArithmeticFlags {
+    /// Requests the counter's CAS identifier (`c`).
+    want_cas();
+    /// Requests the counter's remaining TTL, in seconds (`t`).
+    want_ttl();
+    /// Requests the key to be echoed back (`k`).
+    want_key();
+    /// Updates the TTL of the counter, in seconds (`T`).
+    set_ttl(ttl: i64);
+    /// Creates the counter if it does not exist, with the given TTL in seconds (`N`).
+    autovivify(ttl: i64);
+    /// Sets the initial value of a counter created by [`ArithmeticFlags::autovivify`] (`J`).
+    initial_value(value: u64);
+    /// Only applies the operation if the counter's CAS identifier matches the given one (`C`).
+    compare_cas(cas: u64);
+    /// Marks the key as base64-encoded (`b`).
+    ///
+    /// This only needs to be set for keys which are already encoded, as keys containing bytes
+    /// which cannot be sent as-is are encoded automatically.
+    base64_key();
+    /// Sets an opaque token, which the server echoes back in its response (`O`).
+    opaque(token: u32);
+});
+
+/// The operation applied by a meta set command.
+///
+/// Each mode corresponds to a token of the `M` flag, and matches the classic storage command of
+/// the same name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreMode {
+    /// Stores the value, whether or not the key exists (`MS`).
+    Set,
+    /// Stores the value only if the key does not exist (`ME`).
+    Add,
+    /// Stores the value only if the key exists (`MR`).
+    Replace,
+    /// Appends the value to the data of an existing key (`MA`).
+    Append,
+    /// Prepends the value to the data of an existing key (`MP`).
+    Prepend,
+}
+
+impl StoreMode {
+    /// Returns the mode token for this operation.
+    pub(crate) fn token(self) -> &'static [u8] {
+        match self {
+            Self::Set => b"MS",
+            Self::Add => b"ME",
+            Self::Replace => b"MR",
+            Self::Append => b"MA",
+            Self::Prepend => b"MP",
+        }
+    }
+}
+
+/// The operation applied by a meta arithmetic command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    /// Adds the delta to the counter.
+    Increment,
+    /// Subtracts the delta from the counter.
+    Decrement,
+}
+
+impl ArithmeticMode {
+    /// Returns the mode token for this operation.
+    pub(crate) fn token(self) -> &'static [u8] {
+        match self {
+            Self::Increment => b"MI",
+            Self::Decrement => b"MD",
+        }
+    }
+}
+
+/// The result of a meta arithmetic command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CounterValue {
+    /// Value of the counter after the operation.
+    pub value: u64,
+    /// CAS identifier, if requested with [`ArithmeticFlags::want_cas`].
+    pub cas: Option<u64>,
+    /// Remaining TTL in seconds, if requested with [`ArithmeticFlags::want_ttl`].
+    ///
+    /// A TTL of -1 means the key does not expire.
+    pub ttl: Option<i64>,
+}
+
+/// Whether a client should recompute a value, as reported by a meta get with
+/// [`GetFlags::autovivify`] or [`GetFlags::recache`].
+///
+/// These allow stale-while-revalidate without a stampede: only the client which wins the right to
+/// recache recomputes the value and stores it with a meta set, while the others serve the stale
+/// value, or wait for the fresh one if there is none.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecacheState {
+    /// No client needs to recompute the value.
+    #[default]
+    Fresh,
+    /// This client won the right to recompute the value, and should store the fresh value (`W`).
+    ///
+    /// The value returned alongside, if any, may also be stale.
+    Won,
+    /// The value is stale, and another client has already won the right to recompute it, so the
+    /// stale value can be served in the meantime (`X` without `W`).
+    Stale,
+    /// Another client has already won the right to recompute the value, which is not stale, such
+    /// as when the key was created by [`GetFlags::autovivify`] and has no data yet (`Z` without
+    /// `W` or `X`).
+    Pending,
+}
+
+/// A value returned from a meta command.
+///
+/// Each field is only populated if it was requested through [`GetFlags`] or [`StoreFlags`] and
+/// returned by the server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetaValue {
+    /// The key, if requested with [`GetFlags::want_key`].
+    ///
+    /// Keys returned base64-encoded by the server are decoded.
+    pub key: Option<Vec<u8>>,
+    /// CAS identifier, if requested with [`GetFlags::want_cas`].
+    pub cas: Option<u64>,
+    /// Flags for this key, if requested with [`GetFlags::want_flags`].
+    pub flags: Option<u32>,
+    /// Remaining TTL in seconds, if requested with [`GetFlags::want_ttl`].
+    ///
+    /// A TTL of -1 means the key does not expire.
+    pub ttl: Option<i64>,
+    /// Data for this key, if requested with [`GetFlags::want_value`].
+    pub data: Option<Vec<u8>>,
+    /// Opaque token echoed back by the server, if set with [`GetFlags::opaque`].
+    pub opaque: Option<u32>,
+    /// Whether this client should recompute the value, from the `W`, `X` and `Z` flags returned
+    /// when [`GetFlags::autovivify`] or [`GetFlags::recache`] are set.
+    pub recache: RecacheState,
+}
+
+impl MetaValue {
+    /// Builds a value from the flag tokens and data returned by the server.
+    ///
+    /// Unrecognized or malformed tokens are ignored.  If the key was returned base64-encoded, as
+    /// indicated by the `b` flag, it is decoded.
+    pub(crate) fn from_tokens(tokens: &[Vec<u8>], data: Option<Vec<u8>>) -> Self {
+        let mut value = MetaValue {
+            data,
+            ..Default::default()
+        };
+        let has_flag = |flag: &[u8]| tokens.iter().any(|t| t.as_slice() == flag);
+        let base64_key = has_flag(b"b");
+        value.recache = if has_flag(b"W") {
+            RecacheState::Won
+        } else if has_flag(b"X") {
+            RecacheState::Stale
+        } else if has_flag(b"Z") {
+            RecacheState::Pending
+        } else {
+            RecacheState::Fresh
+        };
+
+        for token in tokens {
+            let (flag, rest) = match token.split_first() {
+                Some((flag, rest)) => (*flag, rest),
+                None => continue,
+            };
+            match flag {
+                b'k' if base64_key => value.key = base64_decode(rest),
+                b'k' => value.key = Some(rest.to_vec()),
+                b'c' => value.cas = btou(rest).ok(),
+                b'f' => value.flags = btou(rest).ok(),
+                b't' => value.ttl = btoi(rest).ok(),
+                b'O' => value.opaque = btou(rest).ok(),
+                _ => {}
+            }
+        }
+
+        value
+    }
+
+    /// Converts this into a [`Value`], as returned by [`Client::get`](crate::Client::get).
+    ///
+    /// The TTL is kept, so [`Value::ttl`] is populated if it was requested.  Returns `None` if the
+    /// key or data were not requested with [`GetFlags::want_key`] and
+    /// [`GetFlags::want_value`], as a [`Value`] cannot be built without them.  Flags which were
+    /// not requested default to 0.
+    pub fn into_value(self) -> Option<Value> {
+        Some(Value {
+            key: self.key?,
+            cas: self.cas,
+            flags: self.flags.unwrap_or(0),
+            data: self.data?,
+            ttl: self.ttl,
+        })
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as padded base64 with the standard alphabet, as memcached expects for keys sent
+/// with the `b` flag.
+fn base64_encode(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | u32::from(*b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i)) as usize & 63]);
+            } else {
+                out.push(b'=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes padded base64 with the standard alphabet, returning `None` if it is malformed.
+fn base64_decode(input: &[u8]) -> Option<Vec<u8>> {
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let last = input.len() / 4;
+    let mut out = Vec::with_capacity(last * 3);
+    for (i, chunk) in input.chunks(4).enumerate() {
+        // Padding may only end the last chunk, which always carries at least one byte.
+        let pad = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if pad > 2 || (pad > 0 && i + 1 != last) {
+            return None;
+        }
+
+        let mut n = 0u32;
+        for b in &chunk[..4 - pad] {
+            n = n << 6 | u32::from(base64_value(*b)?);
+        }
+        n <<= 6 * pad;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - pad]);
+    }
+    Some(out)
+}
+
+fn base64_value(b: u8) -> Option<u8> {
+    match b {
+        b'A'..=b'Z' => Some(b - b'A'),
+        b'a'..=b'z' => Some(b - b'a' + 26),
+        b'0'..=b'9' => Some(b - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        base64_decode, base64_encode, ArithmeticFlags, DeleteFlags, GetFlags, MetaFlags, MetaValue,
+        RecacheState, StoreFlags,
+    };
+    use crate::Value;
+
+    #[test]
+    fn test_flag_tokens() {
+        let mut out = Vec::new();
+        MetaFlags::default().write_tokens(&mut out);
+        assert_eq!(out, b"");
+
+        let mut out = Vec::new();
+        MetaFlags::default()
+            .want_value()
+            .want_flags()
+            .want_cas()
+            .want_ttl()
+            .want_key()
+            .invalidate()
+            .set_ttl(60)
+            .set_flags(5)
+            .autovivify(30)
+            .recache(15)
+            .initial_value(10)
+            .compare_cas(123)
+            .opaque(7)
+            .write_tokens(&mut out);
+        assert_eq!(out, b" v f c t k I T60 F5 N30 R15 J10 C123 O7");
+    }
+
+    #[test]
+    fn test_builder_tokens() {
+        let cases = [
+            (
+                GetFlags::new()
+                    .want_value()
+                    .want_cas()
+                    .recache(15)
+                    .opaque(7)
+                    .0,
+                &b" v c R15 O7"[..],
+            ),
+            (
+                StoreFlags::new()
+                    .want_cas()
+                    .set_ttl(60)
+                    .set_flags(5)
+                    .compare_cas(9)
+                    .0,
+                b" c T60 F5 C9",
+            ),
+            (DeleteFlags::new().invalidate().set_ttl(30).0, b" I T30"),
+            (
+                ArithmeticFlags::new().autovivify(60).initial_value(10).0,
+                b" N60 J10",
+            ),
+        ];
+        for (flags, expected) in cases {
+            let mut out = Vec::new();
+            flags.write_tokens(&mut out);
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_encode_key() {
+        let flags = MetaFlags::default().want_key();
+
+        let (key, encoded_flags) = flags.encode_key(b"foo");
+        assert_eq!(key.as_ref(), b"foo");
+        assert_eq!(encoded_flags.as_ref(), &flags);
+
+        let (key, encoded_flags) = flags.encode_key(b"foo bar\r\n");
+        assert_eq!(key.as_ref(), b"Zm9vIGJhcg0K");
+        assert_eq!(encoded_flags.as_ref(), &flags.clone().base64_key());
+
+        let flags = flags.base64_key();
+        let (key, _) = flags.encode_key(b"Zm9v");
+        assert_eq!(key.as_ref(), b"Zm9v");
+    }
+
+    #[test]
+    fn test_base64() {
+        // Test vectors from RFC 4648, section 10.
+        let cases: &[(&[u8], &[u8])] = &[
+            (b"", b""),
+            (b"f", b"Zg=="),
+            (b"fo", b"Zm8="),
+            (b"foo", b"Zm9v"),
+            (b"foob", b"Zm9vYg=="),
+            (b"fooba", b"Zm9vYmE="),
+            (b"foobar", b"Zm9vYmFy"),
+            (b"\xfb\xff\xfe", b"+//+"),
+        ];
+        for (decoded, encoded) in cases {
+            assert_eq!(&base64_encode(decoded), encoded);
+            assert_eq!(base64_decode(encoded).as_deref(), Some(*decoded));
+        }
+
+        for invalid in [&b"Zg="[..], b"Z===", b"Zg==Zm9v", b"Zm9v!A==", b"Zm=v"] {
+            assert_eq!(base64_decode(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_value_from_base64_tokens() {
+        let tokens = vec![b"b".to_vec(), b"kZm9vIGJhcg0K".to_vec()];
+        assert_eq!(
+            MetaValue::from_tokens(&tokens, None).key,
+            Some(b"foo bar\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_value_from_tokens() {
+        let tokens = vec![
+            b"kfoo".to_vec(),
+            b"c15".to_vec(),
+            b"f42".to_vec(),
+            b"t-1".to_vec(),
+            b"s11".to_vec(),
+            b"O7".to_vec(),
+        ];
+        assert_eq!(
+            MetaValue::from_tokens(&tokens, Some(b"hello world".to_vec())),
+            MetaValue {
+                key: Some(b"foo".to_vec()),
+                cas: Some(15),
+                flags: Some(42),
+                ttl: Some(-1),
+                data: Some(b"hello world".to_vec()),
+                opaque: Some(7),
+                recache: RecacheState::Fresh,
+            }
+        );
+    }
+
+    #[test]
+    fn test_recache_state_from_tokens() {
+        let cases: &[(&[&[u8]], RecacheState)] = &[
+            (&[], RecacheState::Fresh),
+            (&[b"W"], RecacheState::Won),
+            (&[b"W", b"X"], RecacheState::Won),
+            (&[b"X", b"Z"], RecacheState::Stale),
+            (&[b"Z"], RecacheState::Pending),
+        ];
+        for (tokens, expected) in cases {
+            let tokens: Vec<Vec<u8>> = tokens.iter().map(|t| t.to_vec()).collect();
+            assert_eq!(
+                MetaValue::from_tokens(&tokens, None).recache,
+                *expected,
+                "tokens: {:?}",
+                tokens
+            );
+        }
+    }
+
+    #[test]
+    fn test_into_value() {
+        let tokens = vec![b"kfoo".to_vec(), b"f42".to_vec(), b"t60".to_vec()];
+        assert_eq!(
+            MetaValue::from_tokens(&tokens, Some(b"bar".to_vec())).into_value(),
+            Some(Value {
+                key: b"foo".to_vec(),
+                cas: None,
+                flags: 42,
+                data: b"bar".to_vec(),
+                ttl: Some(60),
+            })
+        );
+        assert_eq!(MetaValue::from_tokens(&tokens, None).into_value(), None);
+    }
+}
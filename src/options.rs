@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+/// Options controlling the behaviour of a [`Client`](crate::Client).
+///
+/// The defaults, which [`Client::new`](crate::Client::new) and the other constructors use, are:
+///
+/// - no default TTL or flags, so storage commands given `None` store keys which never expire and
+///   have flags of 0;
+/// - no limit on the length of a single line of a response, or of a `get` command line;
+/// - no limit on the memory retained by the read buffer after a large response;
+/// - a read buffer which adapts to the size of responses, starting at 1KB;
+/// - no timeout on reading responses.
+///
+/// Unless disabled through [`ClientBuilder::tcp_nodelay`](crate::ClientBuilder::tcp_nodelay), TCP
+/// connections are made with `TCP_NODELAY` set.
+///
+/// Options can be changed one at a time with `..Default::default()`, and applied with
+/// [`Client::with_options`](crate::Client::with_options).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientOptions {
+    /// TTL, in seconds, used by storage commands when none is given.
+    ///
+    /// See [`Client::with_default_ttl`](crate::Client::with_default_ttl).
+    pub default_ttl: Option<i64>,
+    /// Client flags used by storage commands when none are given.
+    ///
+    /// See [`Client::with_default_flags`](crate::Client::with_default_flags).
+    pub default_flags: Option<u32>,
+    /// Maximum length of a single line of a response, in bytes.
+    ///
+    /// See [`Client::with_max_line_bytes`](crate::Client::with_max_line_bytes).
+    pub max_line_bytes: Option<usize>,
+    /// Maximum length of a single `get` command line sent by
+    /// [`Client::get_multi`](crate::Client::get_multi), in bytes.
+    ///
+    /// See [`Client::with_max_command_bytes`](crate::Client::with_max_command_bytes).
+    pub max_command_bytes: Option<usize>,
+    /// Size of a response, in bytes, above which the read buffer is shrunk once the response has
+    /// been consumed.
+    ///
+    /// See [`Client::set_read_buffer_high_water`](crate::Client::set_read_buffer_high_water).
+    pub read_buffer_high_water: Option<usize>,
+    /// Smallest number of bytes reserved in the read buffer before each read from the connection.
+    ///
+    /// See [`ClientBuilder::read_buffer_capacity`](crate::ClientBuilder::read_buffer_capacity).
+    pub read_buffer_capacity: Option<usize>,
+    /// How long each read of a response may wait for data from the server.
+    ///
+    /// See [`ClientBuilder::operation_timeout`](crate::ClientBuilder::operation_timeout).
+    pub operation_timeout: Option<Duration>,
+}
@@ -7,13 +7,16 @@ use nom::{
         streaming::{crlf, newline},
     },
     combinator::{map, map_res, opt, value},
-    multi::fold_many0,
+    multi::{fold_many0, many0},
     sequence::{preceded, terminated, tuple},
     IResult,
 };
 use std::str::Utf8Error;
 
-use super::{ErrorKind, KeyMetadata, MetadumpResponse, Response, StatsResponse, Status, Value};
+use super::{
+    ErrorKind, KeyMetadata, MetaResponse, MetadumpResponse, Response, StatsResponse, Status, Value,
+    ValueHeaderResponse, ValueResponse,
+};
 
 pub fn parse_ascii_status(buf: &[u8]) -> IResult<&[u8], Response> {
     terminated(
@@ -34,7 +37,7 @@ fn parse_ascii_error(buf: &[u8]) -> IResult<&[u8], Response> {
         alt((
             value(ErrorKind::NonexistentCommand, tag(b"ERROR")),
             map_res(preceded(tag(b"CLIENT_ERROR "), take_until("\r\n")), |s| {
-                std::str::from_utf8(s).map(|s| ErrorKind::Client(s.to_string()))
+                std::str::from_utf8(s).map(ErrorKind::from_client_error)
             }),
             map_res(preceded(tag(b"SERVER_ERROR "), take_until("\r\n")), |s| {
                 std::str::from_utf8(s).map(|s| ErrorKind::Server(s.to_string()))
@@ -62,23 +65,36 @@ fn parse_bool(buf: &[u8]) -> IResult<&[u8], bool> {
     alt((value(true, tag(b"yes")), value(false, tag(b"no"))))(buf)
 }
 
+/// A line of digits which does not fit in a `u64` is a failure rather than an error, so that it is
+/// reported as an unparseable counter instead of falling through to the other response parsers.
 fn parse_ascii_incrdecr(buf: &[u8]) -> IResult<&[u8], Response> {
-    terminated(map(parse_ascii_u64, Response::IncrDecr), crlf)(buf)
+    let (rest, digits) = terminated(take_while1(is_digit), crlf)(buf)?;
+    match btou(digits) {
+        Ok(n) => Ok((rest, Response::IncrDecr(n))),
+        Err(_) => Err(nom::Err::Failure(nom::error::Error::new(
+            buf,
+            nom::error::ErrorKind::MapRes,
+        ))),
+    }
 }
 
+/// Keys may contain any byte other than whitespace and control characters, so keys which are not
+/// valid UTF-8 are accepted.
 fn is_key_char(chr: u8) -> bool {
-    chr > 32 && chr < 127
+    chr > 32 && chr != 127
 }
 
 fn is_signed_digit(chr: u8) -> bool {
     chr == 45 || (48..=57).contains(&chr)
 }
 
-fn parse_ascii_value(buf: &[u8]) -> IResult<&[u8], Value> {
+/// Key, flags, data length and CAS identifier from the header line of a value.
+type ValueHeaderLine<'a> = (&'a [u8], u32, u64, Option<u64>);
+
+fn parse_ascii_value_header_line(buf: &[u8]) -> IResult<&[u8], ValueHeaderLine<'_>> {
     let kf = take_while1(is_key_char);
     let (buf, (_, key, _, flags, _, len, _, cas, _)) = tuple((
         // VALUE key flags data_len [cas id]\r\n
-        // data block\r\n
         tag("VALUE "),
         kf,
         tag(" "),
@@ -89,6 +105,13 @@ fn parse_ascii_value(buf: &[u8]) -> IResult<&[u8], Value> {
         opt(parse_ascii_u64),
         crlf,
     ))(buf)?;
+    Ok((buf, (key, flags, len, cas)))
+}
+
+fn parse_ascii_value(buf: &[u8]) -> IResult<&[u8], Value> {
+    // VALUE key flags data_len [cas id]\r\n
+    // data block\r\n
+    let (buf, (key, flags, len, cas)) = parse_ascii_value_header_line(buf)?;
     let (buf, data) = terminated(take(len), crlf)(buf)?;
     Ok((
         buf,
@@ -97,6 +120,7 @@ fn parse_ascii_value(buf: &[u8]) -> IResult<&[u8], Value> {
             cas,
             flags,
             data: data.to_vec(),
+            ttl: None,
         },
     ))
 }
@@ -127,6 +151,75 @@ pub fn parse_ascii_response(buf: &[u8]) -> Result<Option<(usize, Response)>, Err
         parse_ascii_data,
     ))(buf);
 
+    match result {
+        Ok((left, response)) => {
+            let n = bufn - left.len();
+            Ok(Some((n, response)))
+        }
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Failure(e)) if e.code == nom::error::ErrorKind::MapRes => Err(
+            ErrorKind::Protocol(Some("unparseable incr result".to_string())),
+        ),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(ErrorKind::Protocol(Some(e.code.description().to_string())))
+        }
+    }
+}
+
+fn parse_ascii_value_status(buf: &[u8]) -> IResult<&[u8], ValueResponse> {
+    map(alt((parse_ascii_status, parse_ascii_error)), |r| match r {
+        Response::Status(s) => ValueResponse::Status(s),
+        _ => unreachable!("statuses and errors are always parsed as a status"),
+    })(buf)
+}
+
+/// Parses a single value, or the end marker, from the response to a retrieval command.
+///
+/// Unlike [`parse_ascii_response`], this does not require the entire response to be buffered, so
+/// each value can be consumed as soon as it has arrived.
+pub fn parse_ascii_value_response(buf: &[u8]) -> Result<Option<(usize, ValueResponse)>, ErrorKind> {
+    let bufn = buf.len();
+    let result = alt((
+        map(parse_ascii_value, ValueResponse::Value),
+        value(ValueResponse::End, tag(b"END\r\n")),
+        parse_ascii_value_status,
+    ))(buf);
+
+    match result {
+        Ok((left, response)) => {
+            let n = bufn - left.len();
+            Ok(Some((n, response)))
+        }
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(ErrorKind::Protocol(Some(e.code.description().to_string())))
+        }
+    }
+}
+
+/// Parses only the header of a single value, without its data block.
+///
+/// The data block, and the `\r\nEND\r\n` that follows it, are left to be consumed by the caller.
+pub fn parse_ascii_value_header(
+    buf: &[u8],
+) -> Result<Option<(usize, ValueHeaderResponse)>, ErrorKind> {
+    let bufn = buf.len();
+    let result = alt((
+        map(parse_ascii_value_header_line, |(key, flags, len, cas)| {
+            ValueHeaderResponse::Header {
+                key: key.to_vec(),
+                flags,
+                len,
+                cas,
+            }
+        }),
+        value(ValueHeaderResponse::End, tag(b"END\r\n")),
+        map(parse_ascii_value_status, |r| match r {
+            ValueResponse::Status(s) => ValueHeaderResponse::Status(s),
+            _ => unreachable!("statuses and errors are always parsed as a status"),
+        }),
+    ))(buf);
+
     match result {
         Ok((left, response)) => {
             let n = bufn - left.len();
@@ -139,6 +232,101 @@ pub fn parse_ascii_response(buf: &[u8]) -> Result<Option<(usize, Response)>, Err
     }
 }
 
+/// Parses the `\r\nEND\r\n` which follows the data block of a single value.
+pub fn parse_ascii_value_trailer(buf: &[u8]) -> Result<Option<(usize, ())>, ErrorKind> {
+    let result: IResult<&[u8], &[u8]> = tag(b"\r\nEND\r\n")(buf);
+
+    match result {
+        Ok((_, trailer)) => Ok(Some((trailer.len(), ()))),
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(ErrorKind::Protocol(Some(e.code.description().to_string())))
+        }
+    }
+}
+
+fn parse_meta_flags(buf: &[u8]) -> IResult<&[u8], Vec<Vec<u8>>> {
+    many0(map(
+        preceded(tag(" "), take_while1(is_key_char)),
+        |f: &[u8]| f.to_vec(),
+    ))(buf)
+}
+
+fn parse_ascii_meta_status(buf: &[u8]) -> IResult<&[u8], MetaResponse> {
+    let status = alt((
+        value(Status::NotFound, tag(b"EN")),
+        value(Status::NotFound, tag(b"NF")),
+        value(Status::NotStored, tag(b"NS")),
+        value(Status::Exists, tag(b"EX")),
+    ));
+
+    map(
+        terminated(tuple((status, parse_meta_flags)), crlf),
+        |(s, _)| MetaResponse::Status(s),
+    )(buf)
+}
+
+fn parse_ascii_meta_header(buf: &[u8]) -> IResult<&[u8], MetaResponse> {
+    map(
+        terminated(preceded(tag(b"HD"), parse_meta_flags), crlf),
+        MetaResponse::Header,
+    )(buf)
+}
+
+fn parse_ascii_meta_value(buf: &[u8]) -> IResult<&[u8], MetaResponse> {
+    // VA <size> <flags>*\r\n
+    // data block\r\n
+    let (buf, (_, len, flags, _)) =
+        tuple((tag("VA "), parse_ascii_u64, parse_meta_flags, crlf))(buf)?;
+    let (buf, data) = terminated(take(len), crlf)(buf)?;
+    Ok((buf, MetaResponse::Value(flags, data.to_vec())))
+}
+
+fn parse_ascii_meta_error(buf: &[u8]) -> IResult<&[u8], MetaResponse> {
+    map(parse_ascii_error, |r| match r {
+        Response::Status(s) => MetaResponse::Status(s),
+        _ => unreachable!("errors are always parsed as a status"),
+    })(buf)
+}
+
+pub fn parse_ascii_meta_response(buf: &[u8]) -> Result<Option<(usize, MetaResponse)>, ErrorKind> {
+    let bufn = buf.len();
+    let result = alt((
+        parse_ascii_meta_header,
+        parse_ascii_meta_value,
+        parse_ascii_meta_status,
+        parse_ascii_meta_error,
+    ))(buf);
+
+    match result {
+        Ok((left, response)) => {
+            let n = bufn - left.len();
+            Ok(Some((n, response)))
+        }
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(ErrorKind::Protocol(Some(e.code.description().to_string())))
+        }
+    }
+}
+
+/// Parses a single line, without its trailing CRLF.
+pub fn parse_ascii_line(buf: &[u8]) -> Result<Option<(usize, Vec<u8>)>, ErrorKind> {
+    let bufn = buf.len();
+    let result: IResult<&[u8], &[u8]> = terminated(take_until("\r\n"), crlf)(buf);
+
+    match result {
+        Ok((left, line)) => {
+            let n = bufn - left.len();
+            Ok(Some((n, line.to_vec())))
+        }
+        Err(nom::Err::Incomplete(_)) => Ok(None),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => {
+            Err(ErrorKind::Protocol(Some(e.code.description().to_string())))
+        }
+    }
+}
+
 fn parse_lru_crawler_errors(buf: &[u8]) -> IResult<&[u8], MetadumpResponse> {
     terminated(
         alt((
@@ -211,6 +399,13 @@ fn parse_stat_entry(buf: &[u8]) -> IResult<&[u8], StatsResponse> {
     )(buf)
 }
 
+fn parse_stats_error(buf: &[u8]) -> IResult<&[u8], StatsResponse> {
+    map(parse_ascii_error, |r| match r {
+        Response::Status(Status::Error(e)) => StatsResponse::Error(e),
+        _ => unreachable!("errors are always parsed as an error status"),
+    })(buf)
+}
+
 pub fn parse_ascii_metadump_response(
     buf: &[u8],
 ) -> Result<Option<(usize, MetadumpResponse)>, ErrorKind> {
@@ -235,7 +430,13 @@ pub fn parse_ascii_metadump_response(
 
 pub fn parse_ascii_stats_response(buf: &[u8]) -> Result<Option<(usize, StatsResponse)>, ErrorKind> {
     let bufn = buf.len();
-    let result = alt((value(StatsResponse::End, tag(b"END\r\n")), parse_stat_entry))(buf);
+    let result = alt((
+        value(StatsResponse::End, tag(b"END\r\n")),
+        value(StatsResponse::Done, tag(b"RESET\r\n")),
+        value(StatsResponse::Done, tag(b"OK\r\n")),
+        parse_stat_entry,
+        parse_stats_error,
+    ))(buf);
 
     match result {
         Ok((left, response)) => {
@@ -252,8 +453,11 @@ pub fn parse_ascii_stats_response(buf: &[u8]) -> Result<Option<(usize, StatsResp
 #[cfg(test)]
 mod tests {
     use super::{
-        parse_ascii_metadump_response, parse_ascii_response, parse_ascii_stats_response, ErrorKind,
-        KeyMetadata, MetadumpResponse, Response, StatsResponse, Status, Value,
+        parse_ascii_line, parse_ascii_meta_response, parse_ascii_metadump_response,
+        parse_ascii_response, parse_ascii_stats_response, parse_ascii_value_header,
+        parse_ascii_value_response, parse_ascii_value_trailer, ErrorKind, KeyMetadata,
+        MetaResponse, MetadumpResponse, Response, StatsResponse, Status, Value,
+        ValueHeaderResponse, ValueResponse,
     };
     use lazy_static::lazy_static;
 
@@ -276,20 +480,27 @@ mod tests {
                 (b"NOT_FOUND\r\n", 11, Response::Status(Status::NotFound)),
                 (b"ERROR\r\n", 7, Response::Status(Status::Error(ErrorKind::NonexistentCommand))),
                 (b"CLIENT_ERROR foo\r\n", 18, Response::Status(Status::Error(ErrorKind::Client(FOO_STR.to_string())))),
+                (b"CLIENT_ERROR bad command line format\r\n", 38, Response::Status(Status::Error(ErrorKind::BadCommandFormat))),
                 (b"SERVER_ERROR bar\r\n", 18, Response::Status(Status::Error(ErrorKind::Server(BAR_STR.to_string())))),
                 (b"42\r\n", 4, Response::IncrDecr(42)),
                 (b"END\r\n", 5, Response::Data(None)),
                 (b"VALUE foo 42 11\r\nhello world\r\nEND\r\n", 35, Response::Data(Some(
-                    vec![Value { key: FOO_KEY.to_vec(), flags: 42, cas: None, data: HELLO_WORLD_DATA.to_vec() }]
+                    vec![Value { key: FOO_KEY.to_vec(), flags: 42, cas: None, data: HELLO_WORLD_DATA.to_vec(), ttl: None }]
+                ))),
+                (b"VALUE foo 42 11 15\r\nhello world\r\nEND\r\n", 38, Response::Data(Some(
+                    vec![Value { key: FOO_KEY.to_vec(), flags: 42, cas: Some(15), data: HELLO_WORLD_DATA.to_vec(), ttl: None }]
                 ))),
                 (b"VALUE foo 42 11\r\nhello world\r\nVALUE bar 43 11 15\r\nhello world\r\nEND\r\n", 68,
                     Response::Data(Some(
                         vec![
-                            Value { key: FOO_KEY.to_vec(), flags: 42, cas: None, data: HELLO_WORLD_DATA.to_vec() },
-                            Value { key: BAR_KEY.to_vec(), flags: 43, cas: Some(15), data: HELLO_WORLD_DATA.to_vec() },
+                            Value { key: FOO_KEY.to_vec(), flags: 42, cas: None, data: HELLO_WORLD_DATA.to_vec(), ttl: None },
+                            Value { key: BAR_KEY.to_vec(), flags: 43, cas: Some(15), data: HELLO_WORLD_DATA.to_vec(), ttl: None },
                         ]
                     ))
                 ),
+                (b"VALUE caf\xc3\xa9\xff 0 11\r\nhello world\r\nEND\r\n", 37, Response::Data(Some(
+                    vec![Value { key: b"caf\xc3\xa9\xff".to_vec(), flags: 0, cas: None, data: HELLO_WORLD_DATA.to_vec(), ttl: None }]
+                ))),
             ]
         };
 
@@ -320,11 +531,31 @@ mod tests {
             ]
         };
 
+        static ref VALID_META_CASES: Vec<(&'static [u8], usize, MetaResponse)> = {
+            vec![
+                // Normal examples: no dangling data, no curveballs.
+                (b"HD\r\n", 4, MetaResponse::Header(vec![])),
+                (b"HD c42 t-1\r\n", 12, MetaResponse::Header(vec![b"c42".to_vec(), b"t-1".to_vec()])),
+                (b"VA 2\r\n10\r\n", 10, MetaResponse::Value(vec![], b"10".to_vec())),
+                (b"VA 11 c15\r\nhello world\r\n", 24, MetaResponse::Value(vec![b"c15".to_vec()], HELLO_WORLD_DATA.to_vec())),
+                (b"EN\r\n", 4, MetaResponse::Status(Status::NotFound)),
+                (b"NF\r\n", 4, MetaResponse::Status(Status::NotFound)),
+                (b"NS\r\n", 4, MetaResponse::Status(Status::NotStored)),
+                (b"EX\r\n", 4, MetaResponse::Status(Status::Exists)),
+                (b"CLIENT_ERROR foo\r\n", 18, MetaResponse::Status(Status::Error(ErrorKind::Client(FOO_STR.to_string())))),
+            ]
+        };
+
         static ref VALID_STATS_CASES: Vec<(&'static [u8], usize, StatsResponse)> = {
             vec![
                 // Normal examples: no dangling data, no curveballs.
                 (b"END\r\n", 5, StatsResponse::End),
                 (b"STAT foobar quux\r\n", 18, StatsResponse::Entry("foobar".to_string(), "quux".to_string())),
+                (b"RESET\r\n", 7, StatsResponse::Done),
+                (b"OK\r\n", 4, StatsResponse::Done),
+                (b"ERROR\r\n", 7, StatsResponse::Error(ErrorKind::NonexistentCommand)),
+                (b"CLIENT_ERROR foo\r\n", 18, StatsResponse::Error(ErrorKind::Client(FOO_STR.to_string()))),
+                (b"SERVER_ERROR bar\r\n", 18, StatsResponse::Error(ErrorKind::Server(BAR_STR.to_string()))),
             ]
         };
     }
@@ -358,6 +589,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_unparseable_incrdecr() {
+        for data in [
+            &b"18446744073709551616\r\n"[..],
+            &b"123456789012345678901234\r\n"[..],
+        ]
+        .iter()
+        {
+            assert_eq!(
+                parse_ascii_response(data),
+                Err(ErrorKind::Protocol(Some(
+                    "unparseable incr result".to_string()
+                )))
+            );
+        }
+    }
+
     #[test]
     fn test_metadump_complete_parsing() {
         // We assume all data has arrived for these tests.
@@ -387,6 +635,132 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_value_response_spanning_many_refills() {
+        // Build a multiget response several times larger than a single buffer refill, and feed it
+        // in refill-sized chunks, consuming each value as soon as it can be parsed.
+        let mut data = Vec::new();
+        for i in 0..64 {
+            data.extend_from_slice(format!("VALUE key{} 0 200\r\n", i).as_bytes());
+            data.extend_from_slice(&[b'a' + (i % 26) as u8; 200]);
+            data.extend_from_slice(b"\r\n");
+        }
+        data.extend_from_slice(b"END\r\n");
+
+        let mut buf = Vec::new();
+        let mut values = Vec::new();
+        let mut ended = false;
+        for chunk in data.chunks(1024) {
+            buf.extend_from_slice(chunk);
+            while let Some((n, response)) = parse_ascii_value_response(&buf).unwrap() {
+                buf.drain(..n);
+                match response {
+                    ValueResponse::Value(v) => values.push(v),
+                    ValueResponse::End => ended = true,
+                    ValueResponse::Status(s) => panic!("unexpected status: {}", s),
+                }
+            }
+
+            // Nothing should be left buffered besides a partial value.
+            assert!(buf.len() < 1024 + 220);
+        }
+
+        assert!(ended);
+        assert!(buf.is_empty());
+        assert_eq!(values.len(), 64);
+        for (i, v) in values.iter().enumerate() {
+            assert_eq!(v.key, format!("key{}", i).into_bytes());
+            assert_eq!(v.data, vec![b'a' + (i % 26) as u8; 200]);
+        }
+    }
+
+    #[test]
+    fn test_value_header_parsing() {
+        let data = b"VALUE foo 42 11 15\r\nhello world\r\nEND\r\n";
+        for i in 0..20 {
+            assert_eq!(parse_ascii_value_header(&data[..i]), Ok(None));
+        }
+        assert_eq!(
+            parse_ascii_value_header(data),
+            Ok(Some((
+                20,
+                ValueHeaderResponse::Header {
+                    key: FOO_KEY.to_vec(),
+                    flags: 42,
+                    len: 11,
+                    cas: Some(15),
+                }
+            )))
+        );
+        assert_eq!(
+            parse_ascii_value_header(b"VALUE foo 0 0\r\n"),
+            Ok(Some((
+                15,
+                ValueHeaderResponse::Header {
+                    key: FOO_KEY.to_vec(),
+                    flags: 0,
+                    len: 0,
+                    cas: None,
+                }
+            )))
+        );
+        assert_eq!(
+            parse_ascii_value_header(b"END\r\n"),
+            Ok(Some((5, ValueHeaderResponse::End)))
+        );
+        assert_eq!(
+            parse_ascii_value_header(b"SERVER_ERROR bar\r\n"),
+            Ok(Some((
+                18,
+                ValueHeaderResponse::Status(Status::Error(ErrorKind::Server(BAR_STR.to_string())))
+            )))
+        );
+
+        assert_eq!(parse_ascii_value_trailer(&data[31..35]), Ok(None));
+        assert_eq!(parse_ascii_value_trailer(&data[31..]), Ok(Some((7, ()))));
+    }
+
+    #[test]
+    fn test_line_parsing() {
+        assert_eq!(parse_ascii_line(b""), Ok(None));
+        assert_eq!(parse_ascii_line(b"OK"), Ok(None));
+        assert_eq!(parse_ascii_line(b"OK\r"), Ok(None));
+        assert_eq!(parse_ascii_line(b"OK\r\n"), Ok(Some((4, b"OK".to_vec()))));
+        assert_eq!(
+            parse_ascii_line(b"VERSION 1.6.7\r\nOK\r\n"),
+            Ok(Some((15, b"VERSION 1.6.7".to_vec())))
+        );
+    }
+
+    #[test]
+    fn test_meta_complete_parsing() {
+        // We assume all data has arrived for these tests.
+        for (data, data_read, expected) in VALID_META_CASES.iter() {
+            let (n, result) = parse_ascii_meta_response(data).unwrap().unwrap();
+
+            assert_eq!(&result, expected);
+            assert_eq!(n, *data_read);
+        }
+    }
+
+    #[test]
+    fn test_meta_incomplete_parsing() {
+        // For each case, we slice down the input data and assert that until we feed the entire
+        // buffer, we don't get a valid response.
+        for (data, data_read, expected) in VALID_META_CASES.iter() {
+            let mut i = 0;
+            while i < *data_read {
+                let subbuf = &data[..i];
+                assert_eq!(parse_ascii_meta_response(subbuf), Ok(None));
+                i += 1;
+            }
+
+            let (n, result) = parse_ascii_meta_response(data).unwrap().unwrap();
+            assert_eq!(&result, expected);
+            assert_eq!(n, *data_read);
+        }
+    }
+
     #[test]
     fn test_stats_complete_parsing() {
         // We assume all data has arrived for these tests.
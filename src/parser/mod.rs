@@ -1,11 +1,18 @@
+use bytes::Bytes;
 use std::fmt;
 mod ascii;
-pub use ascii::{parse_ascii_metadump_response, parse_ascii_response, parse_ascii_stats_response};
+pub use ascii::{
+    parse_ascii_line, parse_ascii_meta_response, parse_ascii_metadump_response,
+    parse_ascii_response, parse_ascii_stats_response, parse_ascii_value_header,
+    parse_ascii_value_response, parse_ascii_value_trailer,
+};
 
 /// A value from memcached.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Value {
     /// The key.
+    ///
+    /// Keys are arbitrary bytes, and are not required to be valid UTF-8.  See [`Value::key_str`].
     pub key: Vec<u8>,
     /// CAS identifier.
     pub cas: Option<u64>,
@@ -15,10 +22,46 @@ pub struct Value {
     pub flags: u32,
     /// Data for this key.
     pub data: Vec<u8>,
+    /// Remaining TTL in seconds, if it was returned by the server.
+    ///
+    /// This is only known for values read with a meta command which requested it, and converted
+    /// with [`MetaValue::into_value`](crate::MetaValue::into_value).  Values read with `get` and
+    /// the other classic commands always have a TTL of `None`.  A TTL of -1 means the key does
+    /// not expire.
+    pub ttl: Option<i64>,
+}
+
+/// A value from memcached whose data is a reference-counted [`Bytes`].
+///
+/// This is returned by [`Client::get_bytes`](crate::Client::get_bytes), which splits the data off
+/// from the client's read buffer rather than copying it.  It is otherwise the same as [`Value`],
+/// into which it can be converted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BytesValue {
+    /// The key.
+    pub key: Vec<u8>,
+    /// CAS identifier.
+    pub cas: Option<u64>,
+    /// Flags for this key.
+    pub flags: u32,
+    /// Data for this key.
+    pub data: Bytes,
+}
+
+impl From<BytesValue> for Value {
+    fn from(value: BytesValue) -> Value {
+        Value {
+            key: value.key,
+            cas: value.cas,
+            flags: value.flags,
+            data: value.data.to_vec(),
+            ttl: None,
+        }
+    }
 }
 
 /// Status of a memcached operation.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Status {
     /// The value was stored.
     Stored,
@@ -37,7 +80,7 @@ pub enum Status {
 }
 
 /// Errors related to a memcached operation.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     /// General error that may or may not have come from either the server or this crate.
     Generic(String),
@@ -47,12 +90,18 @@ pub enum ErrorKind {
     Protocol(Option<String>),
     /// An error from memcached related to CLIENT_ERROR.
     Client(String),
+    /// The server could not parse the command line sent by the client
+    /// (`CLIENT_ERROR bad command line format`).
+    ///
+    /// The server may then read the rest of the command as further commands, so the client is
+    /// poisoned when this is returned.
+    BadCommandFormat,
     /// An error from memcached related to SERVER_ERROR.
     Server(String),
 }
 
 /// Response to a memcached operation.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Response {
     /// The status of a given operation, which may or may not have succeeded.
     Status(Status),
@@ -62,8 +111,50 @@ pub enum Response {
     IncrDecr(u64),
 }
 
+/// A single part of the response to a retrieval command, which may return many values.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValueResponse {
+    /// The status of a given operation, which did not succeed.
+    Status(Status),
+    /// A single value.
+    Value(Value),
+    /// End of the values.
+    End,
+}
+
+/// Header of the response to a retrieval command for a single key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValueHeaderResponse {
+    /// The status of a given operation, which did not succeed.
+    Status(Status),
+    /// Header of a value, with the length of its data block.
+    Header {
+        /// The key.
+        key: Vec<u8>,
+        /// Flags for this key.
+        flags: u32,
+        /// Length of the data block.
+        len: u64,
+        /// CAS identifier.
+        cas: Option<u64>,
+    },
+    /// End of the values, without any value having been found.
+    End,
+}
+
+/// Response to a memcached meta command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetaResponse {
+    /// The status of a given operation, which did not succeed.
+    Status(Status),
+    /// The operation succeeded without returning a value (`HD`), along with any returned flags.
+    Header(Vec<Vec<u8>>),
+    /// The operation succeeded and returned a value (`VA`), along with any returned flags.
+    Value(Vec<Vec<u8>>, Vec<u8>),
+}
+
 /// Metadump response.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum MetadumpResponse {
     /// The server is busy running another LRU crawler operation.
     Busy(String),
@@ -76,18 +167,25 @@ pub enum MetadumpResponse {
 }
 
 /// Stats response.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum StatsResponse {
     /// A stats entry, represented by a key and value.
     Entry(String, String),
     /// End of stats output.
     End,
+    /// A stats subcommand completed without returning entries, e.g. `RESET` or `OK`.
+    Done,
+    /// An error occurred for the given stats command.
+    Error(ErrorKind),
 }
 
 /// Metadata for a given key in a metadump operation.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct KeyMetadata {
     /// The key.
+    ///
+    /// Keys are arbitrary bytes, and are not required to be valid UTF-8.  See
+    /// [`KeyMetadata::key_str`].
     pub key: Vec<u8>,
     /// Expiration time of this key, as a Unix timestamp.
     pub expiration: i64,
@@ -103,6 +201,57 @@ pub struct KeyMetadata {
     pub size: u32,
 }
 
+impl Value {
+    /// Returns the key as a string, if it is valid UTF-8.
+    pub fn key_str(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.key)
+    }
+
+    /// Returns the CAS identifier of this value, if the server returned one.
+    ///
+    /// This is only set for values read with `gets`, `gats` or a meta command requesting it, and
+    /// is `None` for values read with `get`.
+    pub fn cas(&self) -> Option<u64> {
+        self.cas
+    }
+
+    /// Consumes this value, returning only its data.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl KeyMetadata {
+    /// Returns the key as a string, if it is valid UTF-8.
+    pub fn key_str(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.key)
+    }
+}
+
+impl Response {
+    /// Describes this response as unexpected for the given command.
+    pub(crate) fn unexpected(&self, command: &str) -> ErrorKind {
+        let name = match self {
+            Self::Status(_) => "Status",
+            Self::Data(_) => "Data",
+            Self::IncrDecr(_) => "IncrDecr",
+        };
+        ErrorKind::Protocol(Some(format!("unexpected {} response to {}", name, command)))
+    }
+}
+
+impl MetaResponse {
+    /// Describes this response as unexpected for the given command.
+    pub(crate) fn unexpected(&self, command: &str) -> ErrorKind {
+        let name = match self {
+            Self::Status(_) => "Status",
+            Self::Header(_) => "Header",
+            Self::Value(_, _) => "Value",
+        };
+        ErrorKind::Protocol(Some(format!("unexpected {} response to {}", name, command)))
+    }
+}
+
 impl fmt::Display for Status {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -127,11 +276,22 @@ impl fmt::Display for ErrorKind {
                 None => write!(f, "protocol"),
             },
             Self::Client(s) => write!(f, "client: {}", s),
+            Self::BadCommandFormat => write!(f, "client: bad command line format"),
             Self::Server(s) => write!(f, "server: {}", s),
         }
     }
 }
 
+impl ErrorKind {
+    /// Classifies the message of a `CLIENT_ERROR` response.
+    pub(crate) fn from_client_error(msg: &str) -> Self {
+        match msg {
+            "bad command line format" => Self::BadCommandFormat,
+            msg => Self::Client(msg.to_string()),
+        }
+    }
+}
+
 impl From<MetadumpResponse> for Status {
     fn from(resp: MetadumpResponse) -> Self {
         match resp {
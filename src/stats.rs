@@ -0,0 +1,82 @@
+use fxhash::FxHashMap;
+
+/// Commonly used counters from the output of `stats`, parsed into integers.
+///
+/// Any counter missing from the output, or which could not be parsed, is set to 0.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsSummary {
+    /// Number of seconds the server has been running.
+    pub uptime: u64,
+    /// Number of items currently stored.
+    pub curr_items: u64,
+    /// Total number of items stored since the server started.
+    pub total_items: u64,
+    /// Number of bytes currently used to store items.
+    pub bytes: u64,
+    /// Number of keys that have been requested and found.
+    pub get_hits: u64,
+    /// Number of keys that have been requested and not found.
+    pub get_misses: u64,
+    /// Number of valid items removed from the cache to free memory for new items.
+    pub evictions: u64,
+}
+
+impl StatsSummary {
+    /// Builds a summary from the raw output of `stats`.
+    pub(crate) fn from_stats(stats: &FxHashMap<String, String>) -> Self {
+        let counter = |name: &str| {
+            stats
+                .get(name)
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+
+        StatsSummary {
+            uptime: counter("uptime"),
+            curr_items: counter("curr_items"),
+            total_items: counter("total_items"),
+            bytes: counter("bytes"),
+            get_hits: counter("get_hits"),
+            get_misses: counter("get_misses"),
+            evictions: counter("evictions"),
+        }
+    }
+}
+
+/// The output of several stats subcommands, as returned by
+/// [`Client::stats_bundle`](crate::Client::stats_bundle).
+///
+/// As with [`Client::stats`](crate::Client::stats), values are returned as strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsBundle {
+    /// General statistics, from `stats`.
+    pub general: FxHashMap<String, String>,
+    /// Settings of the server, from `stats settings`.
+    pub settings: FxHashMap<String, String>,
+    /// Statistics for the items in each slab class, from `stats items`.
+    pub items: FxHashMap<String, String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatsSummary;
+    use fxhash::FxHashMap;
+
+    #[test]
+    fn test_summary_from_stats() {
+        let mut stats = FxHashMap::default();
+        stats.insert("uptime".to_string(), "100".to_string());
+        stats.insert("curr_items".to_string(), "5".to_string());
+        stats.insert("get_hits".to_string(), "not-a-number".to_string());
+        stats.insert("version".to_string(), "1.6.7".to_string());
+
+        assert_eq!(
+            StatsSummary::from_stats(&stats),
+            StatsSummary {
+                uptime: 100,
+                curr_items: 5,
+                ..Default::default()
+            }
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use async_memcached::{Client, Error, Status};
+use async_memcached::{
+    ArithmeticFlags, ArithmeticMode, Client, CommandError, Error, ErrorKind, GetFlags,
+    RecacheState, StoreFlags, StoreMode, Value,
+};
+use proptest::prelude::*;
 use rand::seq::IteratorRandom;
 use serial_test::{parallel, serial};
 
@@ -104,7 +108,7 @@ async fn test_add_with_a_key_that_already_exists() {
 
     let add_result = client.add(key, "value", None, None).await;
 
-    assert_eq!(add_result, Err(Error::Protocol(Status::NotStored)));
+    assert_eq!(add_result, Err(Error::Command(CommandError::NotStored)));
 }
 
 #[ignore = "Relies on a running memcached server"]
@@ -164,7 +168,7 @@ async fn test_add_multi_with_a_key_that_already_exists() {
     assert!(results[&unset_key_0].is_ok());
     assert!(matches!(
         results[&preset_key_1],
-        Err(Error::Protocol(Status::NotStored))
+        Err(Error::Command(CommandError::NotStored))
     ));
     assert!(results[&unset_key_2].is_ok());
 
@@ -303,7 +307,7 @@ async fn test_set_fails_with_value_too_large() {
 #[parallel]
 async fn test_get_multi() {
     let keys = vec!["mg-key1", "mg-key2", "mg-key3"];
-    let values = vec!["value1", "value2", "value3"];
+    let values = ["value1", "value2", "value3"];
 
     let mut client = setup_client(&keys).await;
 
@@ -328,7 +332,7 @@ async fn test_get_multi() {
 #[parallel]
 async fn test_get_multi_with_nonexistent_key() {
     let mut keys = vec!["mgne-key1", "mgne-key2", "mgne-key3"];
-    let values = vec!["value1", "value2", "value3"];
+    let values = ["value1", "value2", "value3"];
 
     let original_keys_length = keys.len();
 
@@ -364,7 +368,7 @@ async fn test_get_multi_with_nonexistent_key() {
 #[parallel]
 async fn test_get_many_aliases_get_multi_properly() {
     let keys = vec!["get-many-key1", "get-many-key2", "get-many-key3"];
-    let values = vec!["value1", "value2", "value3"];
+    let values = ["value1", "value2", "value3"];
 
     let mut client = setup_client(&keys).await;
 
@@ -669,7 +673,10 @@ async fn test_increment_raises_error_when_key_doesnt_exist() {
 
     let result = client.increment(key, amount).await;
 
-    assert!(matches!(result, Err(Error::Protocol(Status::NotFound))));
+    assert!(matches!(
+        result,
+        Err(Error::Command(CommandError::NotFound))
+    ));
 }
 
 #[ignore = "Relies on a running memcached server"]
@@ -709,7 +716,7 @@ async fn test_increment_on_non_numeric_value() {
 
     assert!(matches!(
         result,
-        Err(Error::Protocol(Status::Error(
+        Err(Error::Command(CommandError::Error(
             async_memcached::ErrorKind::Client(_)
         )))
     ));
@@ -779,7 +786,10 @@ async fn test_decrement_raises_error_when_key_doesnt_exist() {
 
     let result = client.decrement(key, amount).await;
 
-    assert!(matches!(result, Err(Error::Protocol(Status::NotFound))));
+    assert!(matches!(
+        result,
+        Err(Error::Command(CommandError::NotFound))
+    ));
 }
 
 #[ignore = "Relies on a running memcached server"]
@@ -866,3 +876,1688 @@ async fn test_flush_all() {
     let result = client.get(key).await;
     assert!(matches!(result, Ok(None)));
 }
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_counter_creates_missing_key_with_initial_value() {
+    let key = "counter-missing-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.counter(key, 1, 10, 0).await;
+
+    assert_eq!(Ok(10), result);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_counter_applies_delta_to_existing_key() {
+    let key = "counter-existing-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let value: u64 = 10;
+
+    let _ = client.set(key, value, None, None).await;
+
+    assert_eq!(Ok(15), client.counter(key, 5, 0, 0).await);
+    assert_eq!(Ok(12), client.counter(key, -3, 0, 0).await);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_server_time() {
+    let mut client = setup_client(&[]).await;
+
+    let server_time = client
+        .server_time()
+        .await
+        .expect("failed to get server time");
+    let skew = std::time::SystemTime::now()
+        .duration_since(server_time)
+        .unwrap_or_else(|e| e.duration());
+
+    assert!(
+        skew < std::time::Duration::from_secs(60),
+        "skew was {:?}",
+        skew
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[serial]
+async fn test_delete_prefix() {
+    let prefixed_keys = ["delete-prefix:a", "delete-prefix:b", "delete-prefix:c"];
+    let other_key = "delete-prefix-other";
+    let mut keys = prefixed_keys.to_vec();
+    keys.push(other_key);
+
+    let mut client = setup_client(&keys).await;
+
+    for key in &keys {
+        client
+            .set(key, "value", None, None)
+            .await
+            .expect("failed to set");
+    }
+
+    let result = client.delete_prefix(b"delete-prefix:").await;
+    assert_eq!(Ok(prefixed_keys.len()), result);
+
+    for key in prefixed_keys {
+        assert!(matches!(client.get(key).await, Ok(None)));
+    }
+    assert!(matches!(client.get(other_key).await, Ok(Some(_))));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[serial]
+async fn test_delete_prefix_many_keys() {
+    // Enough keys for the deletes to be written across several windows.
+    let keys: Vec<String> = (0..350)
+        .map(|i| format!("delete-prefix-many:{:04}", i))
+        .collect();
+
+    let mut client = setup_client(&keys.iter().map(|k| k.as_str()).collect::<Vec<_>>()).await;
+
+    for key in &keys {
+        client
+            .set(key, "value", None, None)
+            .await
+            .expect("failed to set");
+    }
+
+    let result = client.delete_prefix(b"delete-prefix-many:").await;
+    assert_eq!(Ok(keys.len()), result);
+    assert!(!client.is_poisoned());
+
+    for key in &keys {
+        assert!(matches!(client.get(key).await, Ok(None)));
+    }
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_and_get_with_prefix() {
+    let (prefix, key) = ("with-prefix:", "000042");
+    let full_key = "with-prefix:000042";
+
+    let mut client = setup_client(&[full_key]).await;
+
+    let set_result = client
+        .set_with_prefix(prefix, key, "value", None, Some(7))
+        .await;
+    assert_eq!(Ok(()), set_result);
+
+    // The key is stored in full, as if it had been concatenated.
+    let value = client.get(full_key).await.unwrap().unwrap();
+    assert_eq!(b"value".to_vec(), value.data);
+    assert_eq!(7, value.flags);
+
+    let value = client.get_with_prefix(prefix, key).await.unwrap().unwrap();
+    assert_eq!(full_key.as_bytes(), value.key);
+    assert_eq!(b"value".to_vec(), value.data);
+
+    assert!(matches!(
+        client.get_with_prefix(prefix, "missing").await,
+        Ok(None)
+    ));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_and_get_with_key_writer() {
+    let key = "key-writer:000042";
+
+    let mut client = setup_client(&[key]).await;
+
+    let set_result = client
+        .set_with_key_writer(|k| k.extend_from_slice(key.as_bytes()), "value", None, None)
+        .await;
+    assert_eq!(Ok(()), set_result);
+
+    let get_result = client
+        .get_with_key_writer(|k| {
+            k.extend_from_slice(b"key-writer:");
+            k.extend_from_slice(format!("{:06}", 42).as_bytes());
+        })
+        .await;
+    assert_eq!(b"value".to_vec(), get_result.unwrap().unwrap().data);
+}
+
+#[cfg(feature = "testing")]
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[serial]
+async fn test_dump_keys_with_timeout_abandoned_poisons_client() {
+    use async_memcached::{Fault, FaultInjector};
+
+    let key = "dump-keys-timeout-abandoned-key";
+
+    let client = setup_client(&[key]).await;
+    let mut client = client
+        .wrap_stream(|stream| FaultInjector::new(stream).on("lru_crawler", |_| true, Fault::Drop))
+        .await
+        .expect("failed to wrap stream");
+
+    let mut iter = client
+        .dump_keys_with_timeout(std::time::Duration::from_millis(50))
+        .await
+        .expect("failed to start metadump");
+    assert!(matches!(
+        iter.next().await,
+        Some(Err(Error::Timeout {
+            operation: "metadump",
+            ..
+        }))
+    ));
+    drop(iter);
+
+    // Giving up on the dump must not leave the client looking healthy, as the rest of the dump
+    // would be read as the response to the next command.
+    assert!(client.is_poisoned());
+    assert!(matches!(client.get(key).await, Err(Error::Transport(_))));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[serial]
+async fn test_dump_keys_retry() {
+    let key = "dump-keys-retry-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let mut iter = client
+        .dump_keys_retry(std::time::Duration::from_secs(5))
+        .await
+        .expect("failed to start metadump");
+
+    let mut found = false;
+    while let Some(result) = iter.next().await {
+        if result.expect("metadump failed").key == key.as_bytes() {
+            found = true;
+        }
+    }
+
+    assert!(found, "key should be found in metadump");
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_multi_pipelined() {
+    let keys: Vec<String> = (0..250)
+        .map(|i| format!("get-multi-pipelined-{}", i))
+        .collect();
+    let key_refs: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+
+    let mut client = setup_client(&key_refs).await;
+
+    // Leave every other key unset to check misses are skipped within a chunk.
+    for key in keys.iter().step_by(2) {
+        client
+            .set(key, key.as_str(), None, None)
+            .await
+            .expect("failed to set");
+    }
+
+    let values = client
+        .get_multi_pipelined(&keys, 2)
+        .await
+        .expect("failed to get_multi_pipelined");
+
+    let expected: Vec<&String> = keys.iter().step_by(2).collect();
+    assert_eq!(values.len(), expected.len());
+    for (value, key) in values.iter().zip(expected) {
+        assert_eq!(value.key, key.as_bytes());
+        assert_eq!(value.data, key.as_bytes());
+    }
+}
+
+#[cfg(feature = "testing")]
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_multi_pipelined_failure_poisons_client() {
+    use async_memcached::{Fault, FaultInjector};
+
+    let keys: Vec<String> = (0..250)
+        .map(|i| format!("get-multi-pipelined-failure-{}", i))
+        .collect();
+
+    let client = setup_client(&[]).await;
+    let mut client = client
+        .wrap_stream(|stream| {
+            FaultInjector::new(stream).on(
+                "get",
+                |n| n == 2,
+                Fault::Error(std::io::ErrorKind::ConnectionReset),
+            )
+        })
+        .await
+        .expect("failed to wrap stream");
+
+    // The read fails while a response is still in flight, so the client must not be reused.
+    let result = client.get_multi_pipelined(&keys, 2).await;
+    assert!(
+        matches!(result, Err(Error::Transport(_))),
+        "got {:?}",
+        result
+    );
+    assert!(client.is_poisoned());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_pipeline_raw() {
+    let key = "pipeline-raw-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let lines: &[&[u8]] = &[
+        b"touch pipeline-raw-key 0",
+        b"version",
+        b"delete pipeline-raw-key",
+    ];
+    let responses = client
+        .pipeline_raw(lines)
+        .await
+        .expect("failed to pipeline raw commands");
+
+    assert_eq!(responses.len(), 3);
+    assert_eq!(responses[0], b"NOT_FOUND");
+    assert!(responses[1].starts_with(b"VERSION "));
+    assert_eq!(responses[2], b"NOT_FOUND");
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_reader() {
+    let key = "set-reader-key";
+    let value = "a".repeat(LARGE_PAYLOAD_SIZE / 2);
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client
+        .set_reader(key, value.as_bytes(), value.len() as u64, None, None)
+        .await;
+    assert_eq!(Ok(()), result);
+
+    let result = client.get(key).await;
+    assert_eq!(value.as_bytes(), result.unwrap().unwrap().data);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_reader_with_short_reader() {
+    let key = "set-reader-short-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.set_reader(key, &b"short"[..], 10, None, None).await;
+    assert!(
+        matches!(result, Err(Error::Transport(ref e)) if e.kind() == std::io::ErrorKind::UnexpectedEof),
+        "expected UnexpectedEof, got {:?}",
+        result
+    );
+    assert!(client.is_poisoned());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_reader_with_failing_reader() {
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            _: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Err(std::io::Error::other("reader failed")))
+        }
+    }
+
+    let key = "set-reader-failing-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let reader = (&b"part"[..]).chain(FailingReader);
+    let result = client.set_reader(key, reader, 10, None, None).await;
+    assert!(
+        matches!(result, Err(Error::Transport(ref e)) if e.kind() == std::io::ErrorKind::Other),
+        "expected the reader's error, got {:?}",
+        result
+    );
+
+    // The server is still waiting for the rest of the value, so the next command must not be
+    // sent on this connection.
+    assert!(client.is_poisoned());
+    assert!(matches!(client.get(key).await, Err(Error::Transport(_))));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_to_writer() {
+    let key = "get-to-writer-key";
+    let value = "a".repeat(LARGE_PAYLOAD_SIZE / 2);
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, value.as_str(), None, None)
+        .await
+        .expect("failed to set");
+
+    let mut written = Vec::new();
+    let result = client.get_to_writer(key, &mut written).await;
+    assert_eq!(Ok(Some(value.len())), result);
+    assert_eq!(value.as_bytes(), written);
+
+    // The connection should still be in sync after streaming.
+    let result = client.get(key).await;
+    assert_eq!(value.as_bytes(), result.unwrap().unwrap().data);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_bytes() {
+    let key = "get-bytes-key";
+    let value = "a".repeat(LARGE_PAYLOAD_SIZE / 2);
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, value.as_str(), None, Some(42))
+        .await
+        .expect("failed to set");
+
+    let got = client
+        .get_bytes(key)
+        .await
+        .expect("failed to get_bytes")
+        .expect("key should be found");
+    assert_eq!(key.as_bytes(), got.key);
+    assert_eq!(42, got.flags);
+    assert_eq!(value.as_bytes(), got.data);
+
+    // The data must be unaffected by the read buffer being reused for later responses.
+    assert!(matches!(
+        client.get_bytes("get-bytes-missing-key").await,
+        Ok(None)
+    ));
+    let result = client.get(key).await;
+    assert_eq!(Value::from(got), result.unwrap().unwrap());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_bytes_and_get_to_writer_bad_command_format_poisons_client() {
+    let key = "k".repeat(251);
+
+    let mut client = setup_client(&[]).await;
+    let result = client.get_bytes(&key).await;
+    assert_eq!(Err(Error::Protocol(ErrorKind::BadCommandFormat)), result);
+    assert!(client.is_poisoned());
+
+    let mut client = setup_client(&[]).await;
+    let mut written = Vec::new();
+    let result = client.get_to_writer(&key, &mut written).await;
+    assert_eq!(Err(Error::Protocol(ErrorKind::BadCommandFormat)), result);
+    assert!(client.is_poisoned());
+}
+
+#[cfg(feature = "testing")]
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_bytes_and_get_to_writer_time_out() {
+    use async_memcached::{Fault, FaultInjector};
+
+    let key = "get-streaming-timeout-key";
+    let after = std::time::Duration::from_millis(50);
+
+    let mut client = setup_client(&[key]).await;
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let connect = || async {
+        Client::builder()
+            .operation_timeout(after)
+            .connect("tcp://127.0.0.1:11211")
+            .await
+            .expect("failed to connect")
+            .wrap_stream(|stream| FaultInjector::new(stream).on("get", |_| true, Fault::Drop))
+            .await
+            .expect("failed to wrap stream")
+    };
+
+    let mut client = connect().await;
+    let result = client.get_bytes(key).await;
+    assert!(matches!(
+        result,
+        Err(Error::Timeout {
+            operation: "read",
+            ..
+        })
+    ));
+    assert!(client.is_poisoned());
+
+    let mut client = connect().await;
+    let mut written = Vec::new();
+    let result = client.get_to_writer(key, &mut written).await;
+    assert!(matches!(
+        result,
+        Err(Error::Timeout {
+            operation: "read",
+            ..
+        })
+    ));
+    assert!(client.is_poisoned());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_to_writer_with_nonexistent_key() {
+    let key = "get-to-writer-nonexistent-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let mut written = Vec::new();
+    let result = client.get_to_writer(key, &mut written).await;
+    assert_eq!(Ok(None), result);
+    assert!(written.is_empty());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_get() {
+    let key = "meta-get-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, "value", Some(60), Some(42))
+        .await
+        .expect("failed to set");
+
+    let flags = GetFlags::new()
+        .want_value()
+        .want_flags()
+        .want_cas()
+        .want_ttl()
+        .want_key();
+    let value = client
+        .meta_get(key, &flags)
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be found");
+
+    assert_eq!(value.key, Some(key.as_bytes().to_vec()));
+    assert_eq!(value.data, Some(b"value".to_vec()));
+    assert_eq!(value.flags, Some(42));
+    assert!(value.cas.is_some());
+    assert!(matches!(value.ttl, Some(ttl) if ttl > 0 && ttl <= 60));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_get_with_nonexistent_key() {
+    let key = "meta-get-nonexistent-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.meta_get(key, &GetFlags::new().want_value()).await;
+
+    assert_eq!(Ok(None), result);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_stats_summary() {
+    let mut client = setup_client(&[]).await;
+
+    let summary = client
+        .stats_summary()
+        .await
+        .expect("failed to get stats summary");
+
+    assert!(summary.uptime > 0);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_after_cancelled_get() {
+    let first_key = "cancelled-get-first-key";
+    let second_key = "cancelled-get-second-key";
+
+    let mut client = setup_client(&[first_key, second_key]).await;
+
+    client
+        .set(first_key, "first", None, None)
+        .await
+        .expect("failed to set");
+    client
+        .set(second_key, "second", None, None)
+        .await
+        .expect("failed to set");
+
+    let _ = tokio::time::timeout(std::time::Duration::from_micros(1), client.get(first_key)).await;
+
+    // Whether or not the first get was cancelled, the second must never see its value.
+    match client.get(second_key).await {
+        Ok(value) => assert_eq!(b"second".to_vec(), value.unwrap().data),
+        Err(e) => assert!(client.is_poisoned(), "unexpected error: {:?}", e),
+    }
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_try_append_and_try_prepend() {
+    let key = "try-append-prepend-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    assert_eq!(Ok(false), client.try_append(key, "-suffix").await);
+    assert_eq!(Ok(false), client.try_prepend(key, "prefix-").await);
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    assert_eq!(Ok(true), client.try_append(key, "-suffix").await);
+    assert_eq!(Ok(true), client.try_prepend(key, "prefix-").await);
+
+    let result = client.get(key).await;
+    assert_eq!(
+        b"prefix-value-suffix".to_vec(),
+        result.unwrap().unwrap().data
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_returning_cas() {
+    let key = "set-returning-cas-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let cas = client
+        .set_returning_cas(key, "value", None, Some(7))
+        .await
+        .expect("failed to set_returning_cas");
+
+    let value = client
+        .meta_get(key, &GetFlags::new().want_value().want_cas().want_flags())
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be found");
+
+    assert_eq!(value.cas, Some(cas));
+    assert_eq!(value.flags, Some(7));
+    assert_eq!(value.data, Some(b"value".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_limits() {
+    let mut client = setup_client(&[]).await;
+
+    let limits = client.limits().await.expect("failed to get limits");
+    assert_eq!(limits.max_key_length, 250);
+    assert_eq!(limits.max_relative_ttl, 60 * 60 * 24 * 30);
+    assert!(limits.max_value_size >= 1024);
+
+    // Cached limits are returned without another round trip.
+    assert_eq!(client.limits().await.expect("failed to get limits"), limits);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_arithmetic() {
+    let key = "meta-arithmetic-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client
+        .meta_arithmetic(key, ArithmeticMode::Increment, 1, &ArithmeticFlags::new())
+        .await
+        .expect("failed to meta_arithmetic");
+    assert_eq!(result, None);
+
+    let flags = ArithmeticFlags::new()
+        .autovivify(60)
+        .initial_value(10)
+        .want_ttl();
+    let counter = client
+        .meta_arithmetic(key, ArithmeticMode::Increment, 5, &flags)
+        .await
+        .expect("failed to meta_arithmetic")
+        .expect("counter should be created");
+    assert_eq!(counter.value, 10);
+    assert!(counter.ttl.is_some());
+
+    let counter = client
+        .meta_arithmetic(key, ArithmeticMode::Increment, 5, &flags)
+        .await
+        .expect("failed to meta_arithmetic")
+        .expect("counter should be found");
+    assert_eq!(counter.value, 15);
+
+    let counter = client
+        .meta_arithmetic(
+            key,
+            ArithmeticMode::Decrement,
+            100,
+            &ArithmeticFlags::new().want_cas(),
+        )
+        .await
+        .expect("failed to meta_arithmetic")
+        .expect("counter should be found");
+    assert_eq!(counter.value, 0);
+    assert!(counter.cas.is_some());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_binary_key_round_trip() {
+    let key: &[u8] = b"binary-key-caf\xc3\xa9\xff";
+
+    let mut client = Client::new("tcp://127.0.0.1:11211")
+        .await
+        .expect("Failed to connect to server");
+    client
+        .delete_no_reply(key)
+        .await
+        .expect("Failed to delete key");
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let value = client
+        .get(key)
+        .await
+        .expect("failed to get")
+        .expect("key should be found");
+    assert_eq!(value.key, key.to_vec());
+    assert!(value.key_str().is_err());
+
+    let values = client.get_multi(&[key]).await.expect("failed to get_multi");
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0].key, key.to_vec());
+
+    let meta = client
+        .meta_get(key, &GetFlags::new().want_key())
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be found");
+    assert_eq!(meta.key, Some(key.to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_version() {
+    let mut client = setup_client(&[]).await;
+
+    let version = client.version().await.expect("failed to get version");
+    assert!(version.starts_with(|c: char| c.is_ascii_digit()));
+    assert!(!version.ends_with(char::is_whitespace));
+    assert!(!client.is_poisoned());
+
+    // The connection is left in sync for the next command.
+    assert_eq!(
+        Ok(None),
+        client.get("version-key").await.map(|v| v.map(|v| v.data))
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_sync_after_no_reply_burst() {
+    let key = "sync-no-reply-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, 0_u64, None, None)
+        .await
+        .expect("failed to set");
+    for _ in 0..100 {
+        client
+            .increment_no_reply(key, 1)
+            .await
+            .expect("failed to increment_no_reply");
+    }
+    client.sync().await.expect("failed to sync");
+
+    let value = client
+        .get(key)
+        .await
+        .expect("failed to get")
+        .expect("key should be found");
+    assert_eq!(value.data, b"100".to_vec());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_connect_tcp() {
+    let addr: std::net::SocketAddr = "127.0.0.1:11211".parse().unwrap();
+
+    let mut client = Client::connect_tcp(&[addr])
+        .await
+        .expect("Failed to connect to server");
+
+    client.version().await.expect("failed to get version");
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_store() {
+    let key = "meta-store-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client
+        .meta_store(key, "b", StoreMode::Replace, &StoreFlags::new())
+        .await;
+    assert_eq!(result, Err(Error::Command(CommandError::NotStored)));
+
+    let stored = client
+        .meta_store(key, "b", StoreMode::Add, &StoreFlags::new().want_cas())
+        .await
+        .expect("failed to meta_store");
+    let cas = stored.cas.expect("cas should be returned");
+
+    let result = client
+        .meta_store(key, "b", StoreMode::Add, &StoreFlags::new())
+        .await;
+    assert_eq!(result, Err(Error::Command(CommandError::NotStored)));
+
+    client
+        .meta_store(key, "c", StoreMode::Append, &StoreFlags::new())
+        .await
+        .expect("failed to append");
+    client
+        .meta_store(key, "a", StoreMode::Prepend, &StoreFlags::new())
+        .await
+        .expect("failed to prepend");
+
+    let result = client
+        .meta_store(
+            key,
+            "x",
+            StoreMode::Set,
+            &StoreFlags::new().compare_cas(cas),
+        )
+        .await;
+    assert_eq!(result, Err(Error::Command(CommandError::Exists)));
+
+    let value = client
+        .get(key)
+        .await
+        .expect("failed to get")
+        .expect("key should be found");
+    assert_eq!(value.data, b"abc".to_vec());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_increment_and_decrement_multi() {
+    let keys = [
+        "incr-multi-key-1",
+        "incr-multi-key-2",
+        "incr-multi-missing",
+        "incr-multi-text",
+    ];
+
+    let mut client = setup_client(&keys).await;
+
+    client
+        .set(keys[0], 1_u64, None, None)
+        .await
+        .expect("failed to set");
+    client
+        .set(keys[1], 10_u64, None, None)
+        .await
+        .expect("failed to set");
+    client
+        .set(keys[3], "text", None, None)
+        .await
+        .expect("failed to set");
+
+    let results = client
+        .increment_multi(vec![(keys[0], 1), (keys[1], 5), (keys[2], 1), (keys[3], 1)])
+        .await
+        .expect("failed to increment_multi");
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[keys[0]], Ok(2));
+    assert_eq!(results[keys[1]], Ok(15));
+    assert_eq!(
+        results[keys[2]],
+        Err(Error::Command(CommandError::NotFound))
+    );
+    assert!(matches!(
+        results[keys[3]],
+        Err(Error::Command(CommandError::Error(ErrorKind::Client(_))))
+    ));
+
+    let results = client
+        .decrement_multi(vec![(keys[0], 5), (keys[1], 5)])
+        .await
+        .expect("failed to decrement_multi");
+    assert_eq!(results[keys[0]], Ok(0));
+    assert_eq!(results[keys[1]], Ok(10));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_stats_stream() {
+    let mut client = setup_client(&[]).await;
+
+    let mut iter = client
+        .stats_stream(Some("settings"))
+        .await
+        .expect("failed to stream stats");
+
+    let mut found_item_size_max = false;
+    while let Some(entry) = iter.next().await {
+        let (key, _) = entry.expect("failed to read stats entry");
+        found_item_size_max |= key == "item_size_max";
+    }
+    assert!(found_item_size_max);
+
+    // The whole response was consumed, so the client can be reused.
+    client.version().await.expect("failed to get version");
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_binary_key_is_base64_encoded() {
+    let key: &[u8] = b"meta binary key\r\n\x00\xff";
+
+    let mut client = setup_client(&[]).await;
+
+    client
+        .meta_store(key, "value", StoreMode::Set, &StoreFlags::new())
+        .await
+        .expect("failed to meta_store");
+
+    let value = client
+        .meta_get(key, &GetFlags::new().want_key().want_value())
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be found");
+    assert_eq!(value.key, Some(key.to_vec()));
+    assert_eq!(value.data, Some(b"value".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_measure_rtt() {
+    let mut client = setup_client(&[]).await;
+
+    let rtt = client.measure_rtt().await.expect("failed to measure rtt");
+    assert!(rtt < std::time::Duration::from_secs(5));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_multi_with_misses() {
+    let keys = ["misses-key-1", "misses-key-2", "misses-key-3"];
+
+    let mut client = setup_client(&keys).await;
+
+    let (hits, misses) = client
+        .get_multi_with_misses(&keys)
+        .await
+        .expect("failed to get_multi_with_misses");
+    assert!(hits.is_empty());
+    assert_eq!(misses.len(), 3);
+
+    client
+        .set(keys[1], "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let (hits, misses) = client
+        .get_multi_with_misses(&[keys[0], keys[1], keys[2], keys[0]])
+        .await
+        .expect("failed to get_multi_with_misses");
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[keys[1].as_bytes()].data, b"value".to_vec());
+    assert_eq!(
+        misses,
+        vec![keys[0].as_bytes().to_vec(), keys[2].as_bytes().to_vec()]
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_increment_raw_returns_unparsed_value() {
+    let key = "u64-key-to-increment-raw";
+
+    let mut client = setup_client(&[key]).await;
+
+    let _ = client.set(key, 41u64, None, None).await;
+
+    let result = client.increment_raw(key, 1).await;
+    assert_eq!(Ok(b"42".to_vec()), result);
+
+    let result = client.decrement_raw(key, 2).await;
+    assert_eq!(Ok(b"40".to_vec()), result);
+
+    let result = client.increment_raw("key-does-not-exist-raw", 1).await;
+    assert!(matches!(
+        result,
+        Err(Error::Command(CommandError::NotFound))
+    ));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_get_echoes_opaque_token() {
+    let key = "meta-get-opaque-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let value = client
+        .meta_get(key, &GetFlags::new().want_value().opaque(1234))
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be found");
+
+    assert_eq!(value.opaque, Some(1234));
+    assert_eq!(value.data, Some(b"value".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_self_test() {
+    let mut client = setup_client(&[]).await;
+
+    assert_eq!(client.self_test().await, Ok(()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_bad_command_format_poisons_client() {
+    let mut client = setup_client(&[]).await;
+
+    // Keys longer than 250 bytes are rejected with a bad command line format error, and the value
+    // which follows is then read as another command.
+    let key = "k".repeat(251);
+    let result = client.set(&key, "value", None, None).await;
+
+    assert_eq!(result, Err(Error::Protocol(ErrorKind::BadCommandFormat)));
+    assert!(client.is_poisoned());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_append_past_item_size_max() {
+    let key = "append-past-item-size-max";
+
+    let mut client = setup_client(&[key]).await;
+
+    // Each half fits, but together they exceed the default maximum item size of 1MB.
+    let half = "a".repeat(600 * 1024);
+    client
+        .set(key, half.as_str(), None, None)
+        .await
+        .expect("failed to set");
+
+    let result = client.try_append(key, half.as_str()).await;
+
+    // Depending on the version, the server reports this as the item being too large, or as
+    // running out of memory.
+    assert!(
+        matches!(
+            result,
+            Err(Error::Command(CommandError::ValueTooLarge(_)))
+                | Err(Error::Command(CommandError::Error(ErrorKind::Server(_))))
+        ),
+        "got {:?}",
+        result
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_stats_bundle() {
+    let mut client = setup_client(&[]).await;
+
+    let bundle = client.stats_bundle().await.expect("failed to get stats");
+
+    assert!(bundle.general.contains_key("pid"));
+    assert!(bundle.settings.contains_key("maxconns"));
+
+    // The connection should be left ready for the next command.
+    assert!(client.version().await.is_ok());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_command_errors() {
+    let key = "command-errors-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let err = client.touch(key, 60).await.unwrap_err();
+    assert_eq!(err, Error::Command(CommandError::NotFound));
+    assert!(err.is_command());
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+    let err = client.add(key, "value", None, None).await.unwrap_err();
+    assert_eq!(err, Error::Command(CommandError::NotStored));
+    assert!(err.is_command());
+
+    let err = client.increment(key, 1).await.unwrap_err();
+    assert!(
+        matches!(
+            err,
+            Error::Command(CommandError::Error(ErrorKind::Client(_)))
+        ),
+        "got {:?}",
+        err
+    );
+    assert!(err.is_command());
+
+    // None of these errors affect the connection.
+    assert!(!client.is_poisoned());
+    assert_eq!(client.get(key).await.unwrap().unwrap().data, b"value");
+}
+
+#[cfg(feature = "testing")]
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_transport_errors() {
+    use async_memcached::{Fault, FaultInjector};
+
+    let key = "transport-errors-key";
+
+    let client = setup_client(&[key]).await;
+    let mut client = client
+        .wrap_stream(|stream| {
+            FaultInjector::new(stream).on(
+                "get",
+                |_| true,
+                Fault::Error(std::io::ErrorKind::ConnectionReset),
+            )
+        })
+        .await
+        .expect("failed to wrap stream");
+
+    let err = client.get(key).await.unwrap_err();
+    assert!(
+        matches!(err, Error::Transport(ref e) if e.kind() == std::io::ErrorKind::ConnectionReset),
+        "got {:?}",
+        err
+    );
+    assert!(err.is_transport());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_if_flags() {
+    let key = "set-if-flags-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, "v1", None, Some(1))
+        .await
+        .expect("failed to set");
+
+    let result = client.set_if_flags(key, "v2", 2, None, 3).await;
+    assert_eq!(
+        result,
+        Err(Error::Command(CommandError::FlagsMismatch {
+            expected: 2,
+            actual: 1
+        }))
+    );
+
+    client
+        .set_if_flags(key, "v2", 1, None, 2)
+        .await
+        .expect("failed to set_if_flags");
+
+    let value = client.get(key).await.expect("failed to get").unwrap();
+    assert_eq!(value.data, b"v2".to_vec());
+    assert_eq!(value.flags, 2);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_set_no_reply_read_your_writes() {
+    let key = "async-memcache-test-key-set-no-reply";
+
+    let mut client = setup_client(&[key]).await;
+
+    for _ in 0..10 {
+        let value = format!("{}", rand::random::<u64>());
+        let set_result = client.set_no_reply(key, value.as_str(), None, None).await;
+
+        assert!(
+            set_result.is_ok(),
+            "failed to set_no_reply {}, {:?}",
+            key,
+            set_result
+        );
+
+        let get_result = client.get(key).await;
+
+        match get_result {
+            Ok(Some(get_value)) => assert_eq!(get_value.data, value.as_bytes()),
+            r => panic!("failed to get {}, {:?}", key, r),
+        }
+    }
+
+    assert_eq!(client.sync().await, Ok(()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_touch_if_exists() {
+    let key = "async-memcache-test-key-touch-if-exists";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.touch_if_exists(key, 60).await;
+    assert_eq!(result, Ok(false), "touched missing key {}", key);
+
+    client
+        .set(key, "value", Some(1), None)
+        .await
+        .expect("failed to set key");
+
+    let result = client.touch_if_exists(key, 60).await;
+    assert_eq!(result, Ok(true), "failed to touch {}", key);
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let get_result = client.get(key).await;
+    assert!(
+        matches!(get_result, Ok(Some(_))),
+        "touched key {} expired early, {:?}",
+        key,
+        get_result
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_meta_get_recache_state() {
+    let key = "meta-get-recache-state-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let flags = GetFlags::new().want_value().autovivify(30);
+
+    // The first client to miss wins the right to compute the value, and later ones are told it is
+    // already being computed.
+    let first = client
+        .meta_get(key, &flags)
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be autovivified");
+    assert_eq!(first.recache, RecacheState::Won);
+
+    let second = client
+        .meta_get(key, &flags)
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be autovivified");
+    assert_eq!(second.recache, RecacheState::Pending);
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let fresh = client
+        .meta_get(key, &flags)
+        .await
+        .expect("failed to meta_get")
+        .expect("key should be found");
+    assert_eq!(fresh.recache, RecacheState::Fresh);
+    assert_eq!(fresh.data, Some(b"value".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_item_count() {
+    let key = "item-count-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let item_count = client.item_count().await.expect("failed to get item_count");
+    assert!(
+        item_count >= 1,
+        "expected at least one item, got {}",
+        item_count
+    );
+
+    let total_items = client
+        .total_items()
+        .await
+        .expect("failed to get total_items");
+    assert!(
+        total_items >= item_count,
+        "total_items {} is below item_count {}",
+        total_items,
+        item_count
+    );
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_invalidate() {
+    let key = "meta-invalidate-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.invalidate(key, 30).await;
+    assert_eq!(result, Ok(false), "invalidated missing key {}", key);
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let result = client.invalidate(key, 30).await;
+    assert_eq!(result, Ok(true), "failed to invalidate {}", key);
+
+    // The stale value is still served, and only the first client to get it recomputes it.
+    let flags = GetFlags::new().want_value();
+    let first = client
+        .meta_get(key, &flags)
+        .await
+        .expect("failed to meta_get")
+        .expect("stale key should be found");
+    assert_eq!(first.recache, RecacheState::Won);
+    assert_eq!(first.data, Some(b"value".to_vec()));
+
+    let second = client
+        .meta_get(key, &flags)
+        .await
+        .expect("failed to meta_get")
+        .expect("stale key should be found");
+    assert_eq!(second.recache, RecacheState::Stale);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_multi_all_miss() {
+    let keys = ["mg-all-miss-key1", "mg-all-miss-key2", "mg-all-miss-key3"];
+
+    let mut client = setup_client(&keys).await;
+
+    let results = client.get_multi(&keys).await;
+    assert_eq!(results, Ok(Vec::new()));
+
+    // The stream is left clean for the next command.
+    client
+        .set(keys[0], "value", None, None)
+        .await
+        .expect("failed to set");
+    let values = client.get_multi(&keys).await.expect("failed to get_multi");
+    assert_eq!(values.len(), 1);
+    assert_eq!(values[0].data, b"value".to_vec());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_gets_returns_cas() {
+    let key = "gets-returns-cas-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+
+    let value = client
+        .gets(key)
+        .await
+        .expect("failed to gets")
+        .expect("key should be found");
+    assert!(value.cas.is_some(), "missing CAS identifier for {}", key);
+    assert_eq!(value.data, b"value".to_vec());
+
+    let value = client
+        .get(key)
+        .await
+        .expect("failed to get")
+        .expect("key should be found");
+    assert_eq!(value.cas, None);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_cas() {
+    let key = "cas-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.cas(key, "value", None, None, 1).await;
+    assert_eq!(result, Err(Error::Command(CommandError::NotFound)));
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+    let cas = client
+        .gets(key)
+        .await
+        .expect("failed to gets")
+        .and_then(|value| value.cas)
+        .expect("missing CAS identifier");
+
+    let result = client.cas(key, "updated", None, None, cas).await;
+    assert_eq!(result, Ok(()), "failed to cas {}", key);
+
+    // The key changed with the previous update, so the same CAS identifier no longer matches.
+    let result = client.cas(key, "stale", None, None, cas).await;
+    assert_eq!(result, Err(Error::Command(CommandError::Exists)));
+
+    let value = client.get(key).await.expect("failed to get");
+    assert_eq!(value.map(|v| v.data), Some(b"updated".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_append_prepend() {
+    let key = "append-prepend-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.append(key, "value").await;
+    assert_eq!(result, Err(Error::Command(CommandError::NotStored)));
+    let result = client.prepend(key, "value").await;
+    assert_eq!(result, Err(Error::Command(CommandError::NotStored)));
+
+    client
+        .set(key, "middle", None, Some(7))
+        .await
+        .expect("failed to set");
+    client.append(key, "-end").await.expect("failed to append");
+    client
+        .prepend(key, "start-")
+        .await
+        .expect("failed to prepend");
+
+    let value = client
+        .get(key)
+        .await
+        .expect("failed to get")
+        .expect("key should be found");
+    assert_eq!(value.data, b"start-middle-end".to_vec());
+    assert_eq!(value.flags, 7);
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_replace() {
+    let key = "replace-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.replace(key, "value", None, None).await;
+    assert_eq!(result, Err(Error::Command(CommandError::NotStored)));
+
+    client
+        .set(key, "value", None, None)
+        .await
+        .expect("failed to set");
+    let result = client.replace(key, "replaced", None, None).await;
+    assert_eq!(result, Ok(()), "failed to replace {}", key);
+
+    let value = client.get(key).await.expect("failed to get");
+    assert_eq!(value.map(|v| v.data), Some(b"replaced".to_vec()));
+
+    client
+        .replace_no_reply(key, "replaced again", None, None)
+        .await
+        .expect("failed to replace_no_reply");
+    let value = client.get(key).await.expect("failed to get");
+    assert_eq!(value.map(|v| v.data), Some(b"replaced again".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_gat() {
+    let key = "gat-key";
+
+    let mut client = setup_client(&[key]).await;
+
+    let result = client.gat(60, key).await;
+    assert_eq!(result, Ok(None));
+
+    client
+        .set(key, "value", Some(1), None)
+        .await
+        .expect("failed to set");
+
+    let value = client
+        .gats(60, key)
+        .await
+        .expect("failed to gats")
+        .expect("key should be found");
+    assert_eq!(value.data, b"value".to_vec());
+    assert!(value.cas.is_some(), "missing CAS identifier for {}", key);
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    let value = client.gat(60, key).await.expect("failed to gat");
+    assert_eq!(value.map(|v| v.data), Some(b"value".to_vec()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[serial]
+async fn test_flush_all_delayed_and_no_reply() {
+    let key = "flush-all-delayed-key";
+    let value: u64 = 1;
+
+    let mut client = setup_client(&[key]).await;
+
+    client.set(key, value, None, None).await.unwrap();
+    client.flush_all_delayed(60).await.unwrap();
+    assert!(client.get(key).await.unwrap().is_some());
+
+    client.flush_all_no_reply(None).await.unwrap();
+    assert!(client.get(key).await.unwrap().is_none());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_get_multi_map() {
+    let keys = [
+        "get-multi-map-key1",
+        "get-multi-map-key2",
+        "get-multi-map-key3",
+    ];
+
+    let mut client = setup_client(&keys).await;
+
+    client.set(keys[0], "value1", None, None).await.unwrap();
+    client.set(keys[2], "value3", None, None).await.unwrap();
+
+    let values = client.get_multi_map(&keys).await.unwrap();
+    assert_eq!(values.len(), 2);
+    assert_eq!(values[keys[0].as_bytes()].data, b"value1");
+    assert_eq!(values[keys[2].as_bytes()].data, b"value3");
+    assert!(!values.contains_key(keys[1].as_bytes()));
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_delete_multi() {
+    let keys = [
+        "delete-multi-key1",
+        "delete-multi-key2",
+        "delete-multi-key3",
+    ];
+
+    let mut client = setup_client(&keys).await;
+
+    client.set(keys[0], "value1", None, None).await.unwrap();
+    client.set(keys[2], "value3", None, None).await.unwrap();
+
+    let results = client.delete_multi(keys).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(
+        results[keys[1]],
+        Err(Error::Command(CommandError::NotFound))
+    );
+
+    assert!(client.get(keys[0]).await.unwrap().is_none());
+    assert!(client.get(keys[2]).await.unwrap().is_none());
+}
+
+#[ignore = "Relies on a running memcached server"]
+#[tokio::test]
+#[parallel]
+async fn test_touch_multi() {
+    let keys = ["touch-multi-key1", "touch-multi-key2", "touch-multi-key3"];
+
+    let mut client = setup_client(&keys).await;
+
+    client.set(keys[0], "value1", Some(1), None).await.unwrap();
+    client.set(keys[2], "value3", Some(1), None).await.unwrap();
+
+    let results = client.touch_multi(keys, 60).await.unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[keys[1]], Ok(false));
+
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+    assert!(client.get(keys[0]).await.unwrap().is_some());
+    assert!(client.get(keys[2]).await.unwrap().is_some());
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[ignore = "Relies on a running memcached server"]
+    #[test]
+    fn test_set_get_round_trip(
+        key in prop::collection::vec(33u8..=255, 1..=230),
+        value in prop::collection::vec(any::<u8>(), 0..4096),
+        flags in any::<u32>(),
+    ) {
+        // Values containing framing bytes must come back byte-for-byte.
+        let framed = [&value[..], b"\r\nEND\r\n\0\xff"].concat();
+        // DEL is a control character, so it is not valid in a key.
+        let key = b"round-trip-"
+            .iter()
+            .copied()
+            .chain(key.into_iter().map(|b| if b == 127 { b'~' } else { b }))
+            .collect::<Vec<u8>>();
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let (got, got_framed) = runtime.block_on(async {
+            let mut client = Client::new("tcp://127.0.0.1:11211")
+                .await
+                .expect("Failed to connect to server");
+
+            client.set(&key, value.as_slice(), None, Some(flags)).await.unwrap();
+            let got = client.get(&key).await.unwrap();
+            client.set(&key, framed.as_slice(), None, Some(flags)).await.unwrap();
+            let got_framed = client.get_multi([&key]).await.unwrap();
+            client.delete_no_reply(&key).await.unwrap();
+            (got, got_framed)
+        });
+
+        let expected = Value { key: key.clone(), cas: None, flags, data: value, ttl: None };
+        prop_assert_eq!(got, Some(expected));
+        let expected = Value { key, cas: None, flags, data: framed, ttl: None };
+        prop_assert_eq!(got_framed, vec![expected]);
+    }
+}